@@ -0,0 +1,53 @@
+// Compares the scalar escape-time loop with the vectorized kernels on a
+// strip through the set, where points escape at all sorts of speeds.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use mandelbrot::formula::Mandelbrot;
+use mandelbrot::render::iterate;
+use mandelbrot::simd::{iterate_lanes, iterate_lanes_portable, LANES};
+use num::Complex;
+use std::hint::black_box;
+
+const LIMIT: usize = 1000;
+const RADIUS: f64 = 256.0;
+
+fn points() -> Vec<Complex<f64>> {
+    (0..1024)
+        .map(|i| Complex { re: -2.0 + i as f64 * 2.5 / 1024.0, im: 0.1 + (i % 7) as f64 * 0.05 })
+        .collect()
+}
+
+fn kernels(criterion: &mut Criterion) {
+    let points = points();
+    let origin = Complex { re: 0.0, im: 0.0 };
+    let mut group = criterion.benchmark_group("escape_time");
+    for periodicity in [false, true] {
+        group.bench_with_input(BenchmarkId::new("scalar", periodicity), &periodicity, |b, &periodicity| {
+            b.iter(|| {
+                for &c in &points {
+                    black_box(iterate(&Mandelbrot, origin, c, LIMIT, RADIUS, periodicity));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("portable", periodicity), &periodicity, |b, &periodicity| {
+            b.iter(|| {
+                for c in points.chunks_exact(LANES) {
+                    let c = c.try_into().unwrap();
+                    black_box(iterate_lanes_portable([origin; LANES], c, [true; LANES], LIMIT, RADIUS, periodicity));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("dispatch", periodicity), &periodicity, |b, &periodicity| {
+            b.iter(|| {
+                for c in points.chunks_exact(LANES) {
+                    let c = c.try_into().unwrap();
+                    black_box(iterate_lanes([origin; LANES], c, [true; LANES], LIMIT, RADIUS, periodicity));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, kernels);
+criterion_main!(benches);
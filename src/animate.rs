@@ -0,0 +1,135 @@
+// Animations: every frame goes through the same engines as a single image,
+// sharing what can be shared between them.
+
+use crate::cli::{Args, Engine};
+use crate::{compute_field, Reference};
+use mandelbrot::animation::frame_filename;
+use mandelbrot::fixed::FixedComplex;
+use mandelbrot::palette::ColorMap;
+use mandelbrot::perturbation::reference_orbit;
+use mandelbrot::precise::precision_for;
+use mandelbrot::scene::Frame;
+use mandelbrot::{parse_complex, supersample, write_image_as, Animation, Error, Fractal, Image, Limit, Mode, Params, Scene, ZoomPath};
+use num::Complex;
+
+// Writes frames out as numbered images, and to --animation if given.
+struct Frames<'a> {
+    args: &'a Args,
+    count: usize,
+    animation: Option<Animation>,
+}
+
+impl<'a> Frames<'a> {
+    fn create(args: &'a Args, count: usize) -> Result<Frames<'a>, Error> {
+        let animation = match &args.animation {
+            Some(filename) => Some(Animation::create(filename, args.size, count, args.fps)?),
+            None => None,
+        };
+        Ok(Frames { args, count, animation })
+    }
+
+    fn write(&mut self, frame: usize, image: &Image<[u8; 3]>) -> Result<(), Error> {
+        write_image_as(&frame_filename(self.args.output(), frame), self.args.format(), image, &[])?;
+        if let Some(animation) = &mut self.animation {
+            animation.add_frame(image)?;
+        }
+        if self.args.progress {
+            eprint!("\rrendered {}/{} frames", frame + 1, self.count);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if self.args.progress {
+            eprintln!();
+        }
+        match self.animation {
+            Some(animation) => animation.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn zoom(args: &Args, params: &Params<Fractal>, colors: &ColorMap) -> Result<(), Error> {
+    let frames = args.frames.unwrap_or(1);
+    let start = args.viewport()?;
+    let target_text = args.to.as_deref().or(args.center.as_deref());
+    let target = target_text.and_then(parse_complex).unwrap_or(start.center);
+    let mut path = ZoomPath {
+        start,
+        offset: Complex { re: 0.0, im: 0.0 },
+        end_zoom: args.final_zoom.unwrap_or(start.zoom()),
+        frames,
+        easing: args.easing,
+    };
+
+    // The deepest frame, the last when zooming in and the first when zooming
+    // out, needs the most precision and iterations. Working to those
+    // throughout lets every frame share its center and orbit, placing the
+    // others by their offsets from it.
+    let (first, last) = (path.viewport(0, target), path.viewport(frames - 1, target));
+    let zooming_in = last.zoom() >= first.zoom();
+    let deepest = if zooming_in { last } else { first };
+    let bits = precision_for(args.size, &deepest);
+    let limit = args.max_iter.resolve(&deepest);
+    let precise_target = |bits| target_text
+        .and_then(|text| FixedComplex::parse(text, bits))
+        .unwrap_or_else(|| FixedComplex::from_complex(target, bits));
+    let precise_start = args.precise_center(&start, bits);
+    let deep_target = precise_target(bits);
+    path.offset = FixedComplex { re: &precise_start.re - &deep_target.re, im: &precise_start.im - &deep_target.im }
+        .to_complex();
+    let anchor = |bits| if zooming_in { precise_target(bits) } else { args.precise_center(&start, bits) };
+    let anchor_offset = if zooming_in { Complex { re: 0.0, im: 0.0 } } else { path.offset };
+
+    args.check_engine(params, &deepest)?;
+    let orbit = match args.engine_for(params, &deepest) {
+        Engine::Perturbation => reference_orbit(&anchor(bits), limit, args.radius),
+        _ => Vec::new(),
+    };
+    let mut output = Frames::create(args, frames)?;
+    for frame in 0..frames {
+        let viewport = path.viewport(frame, target);
+        let offset = path.center_offset(frame) - anchor_offset;
+        let center = |bits| anchor(bits).add(&FixedComplex::from_complex(offset, bits));
+        let reference = if orbit.is_empty() { None } else { Some(Reference { orbit: &orbit, offset }) };
+        let field = compute_field(args, params, &viewport, args.max_iter.resolve(&viewport), center, reference, false)?;
+        output.write(frame, &supersample(&field, &viewport, params, colors, &args.supersampling()))?;
+    }
+    output.finish()
+}
+
+pub fn scene(args: &Args, filename: &str, params: &Params<Fractal>, colors: &ColorMap) -> Result<(), Error> {
+    let start = args.viewport()?;
+    let defaults = Frame {
+        center: start.center,
+        zoom: start.zoom(),
+        rotation: args.rotate,
+        max_iter: match args.max_iter {
+            Limit::Fixed(limit) => Some(limit),
+            Limit::Auto => None,
+        },
+        offset: args.offset,
+        julia: args.julia,
+    };
+    let scene = Scene::load(filename, &defaults, args.center.as_deref())?;
+    let frame_params = |values: &Frame| Params { mode: values.julia.map_or(Mode::Mandelbrot, Mode::Julia), ..params.clone() };
+
+    // Check every frame's engine before spending time on any of them.
+    for frame in 0..scene.frames() {
+        let values = scene.frame(frame);
+        args.check_engine(&frame_params(&values), &values.viewport().fit(args.size))?;
+    }
+    let mut output = Frames::create(args, scene.frames())?;
+    for frame in 0..scene.frames() {
+        let values = scene.frame(frame);
+        let viewport = values.viewport().fit(args.size);
+        let params = frame_params(&values);
+        let colors = ColorMap { offset: values.offset, ..colors.clone() };
+        let limit = values.max_iter.unwrap_or_else(|| Limit::Auto.resolve(&viewport));
+        let center = |bits| scene.precise_center(frame, bits);
+        let field = compute_field(args, &params, &viewport, limit, center, None, false)?;
+        output.write(frame, &supersample(&field, &viewport, &params, &colors, &args.supersampling()))?;
+    }
+    output.finish()
+}
@@ -0,0 +1,160 @@
+//! Zoom animations: a sequence of views closing in on a target point.
+
+use crate::error::Error;
+use crate::viewport::Viewport;
+use num::Complex;
+use std::str::FromStr;
+
+/// How progress through an animation speeds up and slows down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+    /// Start slowly.
+    EaseIn,
+    /// Finish slowly.
+    EaseOut,
+    /// Start and finish slowly.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps linear progress `t` in 0..=1 to eased progress.
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+impl FromStr for Easing {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Easing, Error> {
+        match s {
+            "linear" => Ok(Easing::Linear),
+            "ease-in" => Ok(Easing::EaseIn),
+            "ease-out" => Ok(Easing::EaseOut),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            _ => Err(Error::Parse(format!("unknown easing '{}', expected linear, ease-in, ease-out or ease-in-out", s))),
+        }
+    }
+}
+
+#[test]
+fn test_easing() {
+    for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+        assert_eq!((easing.apply(0.0), easing.apply(1.0)), (0.0, 1.0));
+    }
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    assert!(Easing::EaseIn.apply(0.25) < 0.25 && Easing::EaseOut.apply(0.25) > 0.25);
+    assert_eq!("ease-in-out".parse().ok(), Some(Easing::EaseInOut));
+    assert!("bounce".parse::<Easing>().is_err());
+}
+
+/// A zoom from a starting view to a given zoom around a target point.
+///
+/// The zoom grows geometrically, so every frame magnifies the last by the
+/// same factor (before easing), and the center drifts onto the target in
+/// step with it: the target moves across the screen at a steady pace while
+/// the view closes in. Centers are kept as offsets from the target so that
+/// deep zooms can add them to a target held in higher precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ZoomPath {
+    /// The first frame's view.
+    pub start: Viewport,
+    /// The first frame's center minus the target.
+    pub offset: Complex<f64>,
+    /// The last frame's zoom, in the sense of [`Viewport::zoom`].
+    pub end_zoom: f64,
+    /// Number of frames, counting both ends.
+    pub frames: usize,
+    /// Pacing of the zoom.
+    pub easing: Easing,
+}
+
+impl ZoomPath {
+    // Eased progress at frame `i`.
+    fn progress(&self, i: usize) -> f64 {
+        if self.frames < 2 {
+            return 1.0;
+        }
+        self.easing.apply(i as f64 / (self.frames - 1) as f64)
+    }
+
+    /// How many times larger than the first frame's view frame `i`'s is.
+    pub fn scale(&self, i: usize) -> f64 {
+        (self.start.zoom() / self.end_zoom).powf(self.progress(i))
+    }
+
+    /// Frame `i`'s center minus the target.
+    pub fn center_offset(&self, i: usize) -> Complex<f64> {
+        let end = self.start.zoom() / self.end_zoom;
+        if (1.0 - end).abs() < 1e-9 {
+            // No zoom to pace the pan by.
+            return self.offset * (1.0 - self.progress(i));
+        }
+        self.offset * ((self.scale(i) - end) / (1.0 - end))
+    }
+
+    /// The view at frame `i`, centered on `target` plus
+    /// [`ZoomPath::center_offset`].
+    pub fn viewport(&self, i: usize, target: Complex<f64>) -> Viewport {
+        let scale = self.scale(i);
+        Viewport {
+            center: target + self.center_offset(i),
+            width: self.start.width * scale,
+            height: self.start.height * scale,
+            rotation: self.start.rotation,
+        }
+    }
+}
+
+#[test]
+fn test_zoom_path() {
+    let start = Viewport::from_zoom(Complex { re: -0.5, im: 0.0 }, 1.0, 0.0).fit((400, 300));
+    let target = Complex { re: -0.75, im: 0.1 };
+    let path = ZoomPath { start, offset: start.center - target, end_zoom: 1000.0, frames: 31, easing: Easing::Linear };
+
+    assert_eq!(path.viewport(0, target), start);
+    let last = path.viewport(30, target);
+    assert!((last.zoom() - 1000.0).abs() < 1e-9);
+    assert!((last.center - target).norm() < 1e-15);
+    // Every frame magnifies the last by the same factor, ten times per ten frames.
+    let ratio = path.scale(11) / path.scale(10);
+    assert!((path.scale(21) / path.scale(20) - ratio).abs() < 1e-12);
+    assert!((path.scale(10) - 0.1).abs() < 1e-12);
+    // The target keeps its bearing from the center, closing in steadily.
+    let screen = |i: usize| (target - path.viewport(i, target).center) / path.scale(i);
+    assert!((screen(15).arg() - screen(0).arg()).abs() < 1e-9);
+    assert!(screen(15).norm() < screen(0).norm());
+
+    let pan = ZoomPath { end_zoom: 1.0, ..path };
+    assert_eq!(pan.center_offset(30), Complex { re: 0.0, im: 0.0 });
+    assert_eq!(pan.scale(15), 1.0);
+}
+
+/// The filename of frame `frame` of a sequence: the first run of `#` in
+/// `pattern` becomes the frame number, zero-padded to the run's length, or
+/// without one the number goes before the extension.
+pub fn frame_filename(pattern: &str, frame: usize) -> String {
+    if let Some(start) = pattern.find('#') {
+        let width = pattern[start..].chars().take_while(|&c| c == '#').count();
+        return format!("{}{:0width$}{}", &pattern[..start], frame, &pattern[start + width..], width = width);
+    }
+    match pattern.rfind('.') {
+        Some(dot) if !pattern[dot..].contains('/') => format!("{}-{:04}{}", &pattern[..dot], frame, &pattern[dot..]),
+        _ => format!("{}-{:04}", pattern, frame),
+    }
+}
+
+#[test]
+fn test_frame_filename() {
+    assert_eq!(frame_filename("zoom-###.png", 7), "zoom-007.png");
+    assert_eq!(frame_filename("zoom-#.png", 12), "zoom-12.png");
+    assert_eq!(frame_filename("out/zoom.png", 3), "out/zoom-0003.png");
+    assert_eq!(frame_filename("out.d/zoom", 3), "out.d/zoom-0003");
+}
@@ -0,0 +1,161 @@
+//! Supersampling anti-aliasing.
+
+use crate::field::Field;
+use crate::formula::Formula;
+use crate::palette::ColorMap;
+use crate::pixels::{Channel, Image};
+use crate::render::{colorize_as, Params};
+use crate::viewport::{position_to_point, Viewport};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+/// How many samples to take per pixel, and where.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Supersampling {
+    /// Pixels are sampled on a `grid` by `grid` pattern.
+    pub grid: usize,
+    /// Move each sample to a random spot within its grid cell.
+    pub jitter: bool,
+    /// Only supersample pixels whose color differs from a neighbour's.
+    pub adaptive: bool,
+}
+
+// Pixels whose channels differ from a neighbour's by more than this, out of
+// 255, are treated as edges in adaptive mode.
+const EDGE_THRESHOLD: f64 = 8.0;
+
+fn srgb_to_linear<C: Channel>(value: C) -> f64 {
+    let v = value.to_f64() / C::MAX;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb<C: Channel>(value: f64) -> C {
+    let v = value.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    C::from_f64(v * C::MAX)
+}
+
+#[test]
+fn test_linear_round_trip() {
+    for value in 0..=255u8 {
+        assert_eq!(linear_to_srgb::<u8>(srgb_to_linear(value)), value);
+    }
+    // Averaging black and white in linear light is brighter than the
+    // midpoint of their sRGB values.
+    assert_eq!(linear_to_srgb::<u8>((srgb_to_linear(0u8) + srgb_to_linear(255u8)) / 2.0), 188);
+    for value in (0..=u16::MAX).step_by(97) {
+        assert_eq!(linear_to_srgb::<u16>(srgb_to_linear(value)), value);
+    }
+}
+
+fn differs_from_neighbours<C: Channel>(image: &Image<[C; 3]>, (column, row): (usize, usize)) -> bool {
+    let bounds = image.bounds();
+    let here = image[row * bounds.0 + column];
+    let neighbours = [
+        (column.wrapping_sub(1), row),
+        (column + 1, row),
+        (column, row.wrapping_sub(1)),
+        (column, row + 1),
+    ];
+    neighbours.iter()
+        .filter(|&&(x, y)| x < bounds.0 && y < bounds.1)
+        .any(|&(x, y)| {
+            let there = image[y * bounds.0 + x];
+            (0..3).any(|i| (here[i].to_f64() - there[i].to_f64()).abs() > EDGE_THRESHOLD * (C::MAX / 255.0))
+        })
+}
+
+fn supersample_pixel<F: Formula, C: Channel>(pixel: (usize, usize), bounds: (usize, usize), viewport: &Viewport,
+                                             params: &Params<F>, limit: usize, colors: &ColorMap,
+                                             aa: &Supersampling) -> [C; 3] {
+    // Seeding from the pixel keeps jittered renders reproducible.
+    let mut rng = StdRng::seed_from_u64((pixel.1 * bounds.0 + pixel.0) as u64);
+    let mut sum = [0.0; 3];
+    for i in 0..aa.grid {
+        for j in 0..aa.grid {
+            let (dx, dy) = if aa.jitter { (rng.gen::<f64>(), rng.gen::<f64>()) } else { (0.5, 0.5) };
+            // Samples spread over the pixel-sized square centered on the
+            // pixel's own sample point.
+            let position = (pixel.0 as f64 + (i as f64 + dx) / aa.grid as f64 - 0.5,
+                            pixel.1 as f64 + (j as f64 + dy) / aa.grid as f64 - 0.5);
+            let escape = params.escape_time(position_to_point(bounds, position, viewport), limit);
+            let color: [C; 3] = colors.color_as(escape.map(|e| e.smooth));
+            for k in 0..3 {
+                sum[k] += srgb_to_linear(color[k]);
+            }
+        }
+    }
+    let n = (aa.grid * aa.grid) as f64;
+    [linear_to_srgb(sum[0] / n), linear_to_srgb(sum[1] / n), linear_to_srgb(sum[2] / n)]
+}
+
+/// Colors `field`, as [`colorize`] would, but replaces pixels with the
+/// average of `aa.grid` squared samples, iterated afresh with the parameters
+/// the field was computed with.
+pub fn supersample<F: Formula + Sync>(field: &Field, viewport: &Viewport, params: &Params<F>,
+                                      colors: &ColorMap, aa: &Supersampling) -> Image<[u8; 3]> {
+    supersample_as(field, viewport, params, colors, aa)
+}
+
+/// Like [`supersample`], with channels of type `C`.
+pub fn supersample_as<F: Formula + Sync, C: Channel>(field: &Field, viewport: &Viewport, params: &Params<F>,
+                                                     colors: &ColorMap, aa: &Supersampling) -> Image<[C; 3]> {
+    let base = colorize_as(field, colors);
+    if aa.grid <= 1 {
+        return base;
+    }
+
+    let bounds = field.bounds();
+    let mut image = base.clone();
+    image.par_rows_mut()
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, pixel) in band.iter_mut().enumerate() {
+                if aa.adaptive && !differs_from_neighbours(&base, (column, row)) {
+                    continue;
+                }
+                *pixel = supersample_pixel((column, row), bounds, viewport, params, field.limit, colors, aa);
+            }
+        });
+    image
+}
+
+#[test]
+fn test_supersample() {
+    use crate::formula::Mandelbrot;
+    use crate::palette::Palette;
+    use crate::render::{render, Mode};
+    use num::Complex;
+
+    let bounds = (40, 30);
+    let viewport = Viewport::from_zoom(Complex { re: -0.5, im: 0.0 }, 1.5, 0.0).fit(bounds);
+    let params = Params { formula: Mandelbrot, mode: Mode::Mandelbrot, radius: 256.0, interior_checks: true };
+    let colors = ColorMap::new(Palette::builtin("grayscale").unwrap());
+    let field = render(bounds, &viewport, &params, 100).unwrap();
+    let plain = crate::render::colorize(&field, &colors);
+
+    let single = Supersampling { grid: 1, jitter: false, adaptive: false };
+    assert_eq!(supersample(&field, &viewport, &params, &colors, &single), plain);
+
+    let grid = Supersampling { grid: 3, ..single };
+    let smoothed = supersample(&field, &viewport, &params, &colors, &grid);
+    assert_ne!(smoothed, plain);
+    // Deep inside the main cardioid every sample is interior.
+    assert_eq!(smoothed[15 * 40 + 22], colors.interior);
+
+    let adaptive = Supersampling { adaptive: true, ..grid };
+    let edges = supersample(&field, &viewport, &params, &colors, &adaptive);
+    for i in 0..plain.len() {
+        let pixel = (i % bounds.0, i / bounds.0);
+        if !differs_from_neighbours(&plain, pixel) {
+            assert_eq!(edges[i], plain[i]);
+        } else {
+            assert_eq!(edges[i], smoothed[i]);
+        }
+    }
+
+    let jitter = Supersampling { jitter: true, ..grid };
+    assert_eq!(supersample(&field, &viewport, &params, &colors, &jitter),
+               supersample(&field, &viewport, &params, &colors, &jitter));
+}
@@ -0,0 +1,578 @@
+use clap::{ArgGroup, Parser, ValueEnum};
+use num::Complex;
+use std::path::Path;
+use mandelbrot::fixed::FixedComplex;
+use mandelbrot::perturbation::MIN_PIXEL_SPACING;
+use mandelbrot::precise::needs_precision;
+use mandelbrot::palette::{self, ColorMap, Palette, Wrap};
+use mandelbrot::{parse_complex, parse_pair, Easing, Error, Format, Fractal, Limit, Mode, Params, Strategy, Supersampling, TileOrder, Tiling, Viewport};
+
+#[derive(Parser, Clone, Debug)]
+#[command(group(ArgGroup::new("sequence").args(["frames", "scene"])))]
+#[command(version, about = "Render escape-time fractals to images", args_override_self = true)]
+#[command(after_help = "\
+Fractals: mandelbrot, multibrot:N (N > 1), burning-ship, tricorn (mandelbar), celtic
+Palettes: grayscale, viridis, magma, classic, or a gradient file of `POSITION COLOR` lines
+
+Examples:
+  mandelbrot -o mandel.png -s 1000x750 --upper-left=-1.20,0.35 --lower-right=-1,0.20
+  mandelbrot -o julia.png --julia=-0.8,0.156 --center 0,0 --zoom 1.2
+  mandelbrot -o ship.png --fractal burning-ship --center=-1.75,-0.045 --zoom 40
+  mandelbrot -o 'zoom-###.png' --frames 120 --to=-0.7436,0.1318 --final-zoom 1e6 -i auto --animation zoom.gif
+  mandelbrot --job batch.toml
+  mandelbrot --rerender julia.png -s 4000x3000 -o julia-large.png
+  mandelbrot --explore -o view.png --fractal burning-ship
+  mandelbrot --preview -s 1600x1200 --center=-0.75,0.1
+
+Job files set options by their long names, as TOML (`zoom = 40`) or JSON
+(`{\"zoom\": 40}`); a `[[job]]` table per image makes a batch, and options
+outside them apply to every job. Options on the command line override the file.
+
+Exit status: 0 on success, 1 if some jobs of a batch failed, 2 for a bad
+command line, 3 for a file or value that doesn't parse, 4 for an impossible
+view, 5 for a file that can't be read or written, 6 for an encoding error.")]
+pub struct Args {
+    /// Image file to write, or the name pattern of animation frames; the
+    /// extension picks the format unless --format is given
+    #[arg(short, long, value_name = "FILE", required_unless_present_any = ["job", "rerender", "explore", "preview"])]
+    pub output: Option<String>,
+
+    /// Output format: png, ppm, pgm, tiff, jpeg, webp, or pfm for a float
+    /// map of smooth iteration counts (default: from the extension, or png)
+    #[arg(long, value_name = "FORMAT")]
+    pub format: Option<Format>,
+
+    /// Bits per color channel: 8, or 16 for png, ppm, pgm and tiff
+    #[arg(long, value_name = "BITS", default_value_t = 8, value_parser = parse_depth)]
+    pub depth: u32,
+
+    /// Image size in pixels
+    #[arg(short, long, value_name = "WxH", default_value = "1000x750", value_parser = parse_size)]
+    pub size: (usize, usize),
+
+    /// Point at the middle of the image; give as many digits as deep zooms need
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parse_center,
+          conflicts_with_all = ["upper_left", "lower_right"])]
+    pub center: Option<String>,
+
+    /// Magnification; at zoom 1 the shorter side of the view is four units
+    #[arg(long, value_name = "Z", conflicts_with_all = ["upper_left", "lower_right", "width"])]
+    pub zoom: Option<f64>,
+
+    /// Width of the view in the complex plane, instead of --zoom
+    #[arg(long, value_name = "W", conflicts_with_all = ["upper_left", "lower_right"])]
+    pub width: Option<f64>,
+
+    /// Turn the view counter-clockwise by this many degrees
+    #[arg(long, value_name = "DEGREES", default_value_t = 0.0, allow_negative_numbers = true,
+          conflicts_with_all = ["upper_left", "lower_right"])]
+    pub rotate: f64,
+
+    /// Upper left corner of the view
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parse_point,
+          requires = "lower_right")]
+    pub upper_left: Option<Complex<f64>>,
+
+    /// Lower right corner of the view
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parse_point,
+          requires = "upper_left")]
+    pub lower_right: Option<Complex<f64>>,
+
+    /// Fractal formula
+    #[arg(short, long, value_name = "NAME", default_value = "mandelbrot")]
+    pub fractal: Fractal,
+
+    /// Render the Julia set for this constant instead of the parameter plane
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parse_point)]
+    pub julia: Option<Complex<f64>>,
+
+    /// Escape radius; larger values give smoother coloring
+    #[arg(long, value_name = "R", default_value_t = 256.0)]
+    pub radius: f64,
+
+    /// Iteration limit, or `auto` to pick one from the zoom depth
+    #[arg(short = 'i', long, visible_alias = "iterations", value_name = "N|auto", default_value = "255")]
+    pub max_iter: Limit,
+
+    /// Built-in palette name or gradient file
+    #[arg(short, long, value_name = "NAME|FILE", default_value = "classic")]
+    pub palette: String,
+
+    /// How colors repeat outside the palette: cyclic or clamp
+    #[arg(long, value_name = "MODE", default_value = "cyclic")]
+    pub wrap: Wrap,
+
+    /// Shift the palette by this fraction of its length
+    #[arg(long, value_name = "F", default_value_t = 0.0, allow_negative_numbers = true)]
+    pub offset: f64,
+
+    /// Iterations one pass through the palette spans, whatever the
+    /// iteration limit
+    #[arg(long, value_name = "N", default_value_t = 256.0)]
+    pub period: f64,
+
+    /// Color of points inside the set, as #rrggbb or r,g,b
+    #[arg(long, value_name = "COLOR", default_value = "#000000", value_parser = parse_color)]
+    pub interior: [u8; 3],
+
+    /// Supersample each pixel on an NxN grid (double engine only, so not for
+    /// zooms deep enough to need another)
+    #[arg(long, value_name = "N", default_value_t = 1, conflicts_with = "load_field")]
+    pub aa: usize,
+
+    /// Place supersamples randomly within their grid cells
+    #[arg(long, requires = "aa")]
+    pub jitter: bool,
+
+    /// Only supersample pixels that differ from their neighbours
+    #[arg(long, requires = "aa")]
+    pub adaptive: bool,
+
+    /// Also write the raw iteration field to this file
+    #[arg(long, value_name = "FIELD")]
+    pub save_field: Option<String>,
+
+    /// Color a previously saved iteration field instead of computing one
+    #[arg(long, value_name = "FIELD", conflicts_with_all = ["save_field", "center", "upper_left"])]
+    pub load_field: Option<String>,
+
+    /// How to iterate; `auto` switches to perturbation, or to arbitrary
+    /// precision for fractals perturbation can't do, when pixels are too
+    /// close together for f64
+    #[arg(long, value_name = "ENGINE", value_enum, default_value_t = Engine::Auto)]
+    pub engine: Engine,
+
+    /// Don't skip iterations with series approximation when perturbing
+    #[arg(long)]
+    pub no_series: bool,
+
+    /// Iterate every point to the limit, without the cardioid, bulb and
+    /// periodicity shortcuts for points in the set
+    #[arg(long)]
+    pub no_interior_checks: bool,
+
+    /// Print how many iterations were computed and how many interior
+    /// checks saved (double engine only)
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Edge length in pixels of the square tiles work is split into
+    #[arg(long, value_name = "N", default_value_t = 64)]
+    pub tile_size: usize,
+
+    /// Which tiles to render first: center, cost (most expensive first) or
+    /// rows
+    #[arg(long, value_name = "ORDER", default_value = "center")]
+    pub tile_order: TileOrder,
+
+    /// Which pixels to iterate: brute-force, or mariani-silver or
+    /// boundary-trace to fill regions of equal count from their edges
+    #[arg(long, value_name = "STRATEGY", default_value = "brute-force")]
+    pub strategy: Strategy,
+
+    /// Report progress as tiles finish
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Render a zoom of N frames instead of one image, from the view given
+    /// to --final-zoom around --to; a run of # in the output name becomes
+    /// the frame number
+    #[arg(long, value_name = "N", requires = "final_zoom", conflicts_with_all = ["load_field", "save_field"])]
+    pub frames: Option<usize>,
+
+    /// Point the zoom closes in on (default: the starting center)
+    #[arg(long, value_name = "RE,IM", allow_hyphen_values = true, value_parser = parse_center, requires = "frames")]
+    pub to: Option<String>,
+
+    /// Zoom of the last frame
+    #[arg(long, value_name = "Z", requires = "frames")]
+    pub final_zoom: Option<f64>,
+
+    /// Pacing of the zoom: linear, ease-in, ease-out or ease-in-out
+    #[arg(long, value_name = "EASING", default_value = "linear")]
+    pub easing: Easing,
+
+    /// Render the keyframed animation in this scene file, starting from the
+    /// view and coloring given by the other options; output is named as for
+    /// --frames
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["load_field", "save_field"])]
+    pub scene: Option<String>,
+
+    /// Also write the frames of --frames or --scene as an animated GIF
+    /// (.gif) or PNG (any other extension)
+    #[arg(long, value_name = "FILE", requires = "sequence")]
+    pub animation: Option<String>,
+
+    /// Frame rate of --animation
+    #[arg(long, value_name = "N", default_value_t = 25)]
+    pub fps: u16,
+
+    /// Number of worker threads (default: one per core)
+    #[arg(short = 'j', long, value_name = "N")]
+    pub threads: Option<usize>,
+
+    /// Render the job, or batch of jobs, described in this TOML or JSON file
+    #[arg(long, value_name = "FILE")]
+    pub job: Option<String>,
+
+    /// Render the image in this PNG again from the options stored in it,
+    /// changed by any given here; needs --output, and --size for a larger
+    /// image
+    #[arg(long, value_name = "PNG", conflicts_with = "job")]
+    pub rerender: Option<String>,
+
+    /// Also write the options that reproduce the output, as a job file named
+    /// after it with `.toml` added (always done for --job)
+    #[arg(long)]
+    pub write_job: bool,
+
+    /// Open a window onto the view to explore it with the mouse and
+    /// keyboard; `s` renders what it shows to --output (needs a build with
+    /// the `explore` feature)
+    #[arg(long, conflicts_with_all = ["job", "rerender", "sequence", "load_field", "save_field"])]
+    pub explore: bool,
+
+    /// Show the view in the terminal to move around with the keyboard; `s`
+    /// renders what it shows to --output, or preview.png, and quitting
+    /// prints the corners that render it at --size
+    #[arg(long, conflicts_with_all = ["job", "rerender", "sequence", "load_field", "save_field", "explore"])]
+    pub preview: bool,
+}
+
+// A clap error as an [`Error`], keeping just its first line.
+pub fn clap_error(err: clap::Error) -> Error {
+    let message = err.to_string();
+    Error::Invalid(message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string())
+}
+
+// The widest or tallest image we'll try to render. Far past what fits in
+// memory, but small enough that the pixel count can't overflow.
+const MAX_SIDE: usize = 1 << 20;
+
+fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    match parse_pair::<usize>(s, 'x') {
+        None => Err(format!("invalid size '{}', expected WIDTHxHEIGHT", s)),
+        Some((0, _)) | Some((_, 0)) => Err("image dimensions must be non-zero".to_string()),
+        Some((width, height)) if width > MAX_SIDE || height > MAX_SIDE => {
+            Err(format!("image dimensions must be at most {}, not {}", MAX_SIDE, s))
+        }
+        Some(size) => Ok(size),
+    }
+}
+
+fn parse_depth(s: &str) -> Result<u32, String> {
+    match s {
+        "8" => Ok(8),
+        "16" => Ok(16),
+        _ => Err(format!("invalid depth '{}', expected 8 or 16", s)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Engine {
+    Auto,
+    Double,
+    Precise,
+    Perturbation,
+}
+
+fn parse_point(s: &str) -> Result<Complex<f64>, String> {
+    parse_complex(s).ok_or_else(|| format!("invalid point '{}', expected RE,IM", s))
+}
+
+// Whether the perturbation engine can render `params`, which it only can
+// for the Mandelbrot set itself.
+pub fn perturbable(params: &Params<Fractal>) -> bool {
+    params.formula == Fractal::Mandelbrot && params.mode == Mode::Mandelbrot
+}
+
+// `point`, if both its parts are finite; `what` names it in the error.
+fn finite_point(point: Complex<f64>, what: &str) -> Result<Complex<f64>, Error> {
+    if !(point.re.is_finite() && point.im.is_finite()) {
+        return Err(Error::Viewport(format!("{} must be a finite point, not {},{}", what, point.re, point.im)));
+    }
+    Ok(point)
+}
+
+// Centers stay as text until we know how much precision they need.
+fn parse_center(s: &str) -> Result<String, String> {
+    parse_point(s)?;
+    Ok(s.to_string())
+}
+
+fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    palette::parse_color(s).ok_or_else(|| format!("invalid color '{}', expected #rrggbb or r,g,b", s))
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("640x480"), Ok((640, 480)));
+    assert!(parse_size("0x480").is_err());
+    assert!(parse_size("640x0").is_err());
+    assert!(parse_size("640").is_err());
+    assert!(parse_size("4294967296x4294967297").is_err());
+    assert_eq!(parse_size("1048576x1"), Ok((1 << 20, 1)));
+}
+
+impl Args {
+    // The output file; `validate` checks there is one.
+    pub fn output(&self) -> &str {
+        self.output.as_deref().unwrap_or_default()
+    }
+
+    pub fn format(&self) -> Format {
+        self.format.or_else(|| Format::from_filename(self.output())).unwrap_or(Format::Png)
+    }
+
+    pub fn mode(&self) -> Mode {
+        match self.julia {
+            None => Mode::Mandelbrot,
+            Some(c) => Mode::Julia(c),
+        }
+    }
+
+    pub fn params(&self) -> Params<Fractal> {
+        Params { formula: self.fractal, mode: self.mode(), radius: self.radius, interior_checks: !self.no_interior_checks }
+    }
+
+    // The view from either the corner options or the center with a zoom or
+    // width, widened to match the image's aspect ratio.
+    pub fn viewport(&self) -> Result<Viewport, Error> {
+        if let (Some(upper_left), Some(lower_right)) = (self.upper_left, self.lower_right) {
+            finite_point(upper_left, "the upper left corner")?;
+            finite_point(lower_right, "the lower right corner")?;
+            if upper_left.re >= lower_right.re || upper_left.im <= lower_right.im {
+                return Err(Error::Viewport("the upper left corner must be above and to the left of the lower right corner".to_string()));
+            }
+            return Ok(Viewport::from_corners(upper_left, lower_right).fit(self.size));
+        }
+
+        let center = self.center.as_deref().and_then(parse_complex)
+            .map(|center| finite_point(center, "the center")).transpose()?
+            .unwrap_or(Complex { re: -0.5, im: 0.0 });
+        let rotation = self.rotate.to_radians();
+        if !rotation.is_finite() {
+            return Err(Error::Viewport(format!("invalid rotation {}", self.rotate)));
+        }
+        let viewport = match (self.zoom, self.width) {
+            (_, Some(width)) if !(width.is_finite() && width > 0.0) => {
+                return Err(Error::Viewport(format!("view width must be a positive number, not {}", width)));
+            }
+            (_, Some(width)) => Viewport { center, width, height: 0.0, rotation },
+            (Some(zoom), _) if !(zoom.is_finite() && zoom > 0.0) => {
+                return Err(Error::Viewport(format!("zoom must be a positive number, not {}", zoom)));
+            }
+            (zoom, None) => Viewport::from_zoom(center, zoom.unwrap_or(1.0), rotation),
+        };
+        Ok(viewport.fit(self.size))
+    }
+
+    // The center of `viewport` with `bits` fraction bits, keeping every digit
+    // given on the command line.
+    pub fn precise_center(&self, viewport: &Viewport, bits: u32) -> FixedComplex {
+        self.center.as_deref()
+            .and_then(|text| FixedComplex::parse(text, bits))
+            .unwrap_or_else(|| FixedComplex::from_complex(viewport.center, bits))
+    }
+
+    // The engine to render `params` in `viewport` with, resolving `auto`.
+    pub fn engine_for(&self, params: &Params<Fractal>, viewport: &Viewport) -> Engine {
+        match self.engine {
+            Engine::Auto if !needs_precision(self.size, viewport) => Engine::Double,
+            Engine::Auto if perturbable(params) && viewport.pixel_size(self.size) >= MIN_PIXEL_SPACING => {
+                Engine::Perturbation
+            }
+            Engine::Auto => Engine::Precise,
+            engine => engine,
+        }
+    }
+
+    pub fn supersampling(&self) -> Supersampling {
+        Supersampling { grid: self.aa, jitter: self.jitter, adaptive: self.adaptive }
+    }
+
+    pub fn tiling(&self) -> Tiling {
+        Tiling { size: self.tile_size, order: self.tile_order, strategy: self.strategy }
+    }
+
+    pub fn colors(&self) -> Result<ColorMap, Error> {
+        let palette = match Palette::builtin(&self.palette) {
+            Some(palette) => palette,
+            None if !Path::new(&self.palette).exists() => {
+                return Err(Error::Parse(format!("unknown palette '{}', expected one of {} or a gradient file",
+                                                self.palette, palette::BUILTIN_NAMES.join(", "))));
+            }
+            None => Palette::load(&self.palette)?,
+        };
+        let mut colors = ColorMap::new(palette);
+        colors.wrap = self.wrap;
+        colors.offset = self.offset;
+        colors.period = self.period;
+        colors.interior = self.interior;
+        Ok(colors)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.output.is_none() {
+            return Err(Error::Invalid("no output file given".to_string()));
+        }
+        if !(self.radius.is_finite() && self.radius > 0.0) {
+            return Err(Error::Invalid(format!("escape radius must be a positive number, not {}", self.radius)));
+        }
+        if !(self.period.is_finite() && self.period > 0.0) {
+            return Err(Error::Invalid(format!("palette period must be a positive number, not {}", self.period)));
+        }
+        if !self.offset.is_finite() {
+            return Err(Error::Invalid(format!("palette offset must be a finite number, not {}", self.offset)));
+        }
+        if self.aa == 0 {
+            return Err(Error::Invalid("--aa must be at least 1".to_string()));
+        }
+        let sequence = self.frames.is_some() || self.scene.is_some();
+        if self.depth == 16 && !self.format().supports_16_bit() {
+            return Err(Error::Invalid("--depth 16 needs png, ppm, pgm or tiff output".to_string()));
+        }
+        if self.depth == 16 && sequence {
+            return Err(Error::Invalid("animation frames can only be written with 8-bit channels".to_string()));
+        }
+        if self.format() == Format::Pfm && sequence {
+            return Err(Error::Invalid("pfm output is only supported for single images".to_string()));
+        }
+        if self.format() == Format::Pfm && self.aa > 1 {
+            return Err(Error::Invalid("--aa can't be used with pfm output, which holds one iteration count per pixel".to_string()));
+        }
+        if self.tile_size == 0 {
+            return Err(Error::Invalid("tile size must be at least 1".to_string()));
+        }
+        if self.frames == Some(0) {
+            return Err(Error::Invalid("frame count must be at least 1".to_string()));
+        }
+        if let Some(zoom) = self.final_zoom {
+            if !(zoom.is_finite() && zoom > 0.0) {
+                return Err(Error::Viewport(format!("zoom must be a positive number, not {}", zoom)));
+            }
+        }
+        if let Some(to) = self.to.as_deref().and_then(parse_complex) {
+            finite_point(to, "the --to point")?;
+        }
+        if self.fps == 0 {
+            return Err(Error::Invalid("frame rate must be at least 1".to_string()));
+        }
+        if self.threads == Some(0) {
+            return Err(Error::Invalid("thread count must be at least 1".to_string()));
+        }
+        self.check_engine(&self.params(), &self.viewport()?)
+    }
+
+    // Fails if the engine for `params` in `viewport` can't render them as
+    // asked: perturbation only does the Mandelbrot set, and --aa iterates
+    // its samples in f64, so needs the double engine.
+    pub fn check_engine(&self, params: &Params<Fractal>, viewport: &Viewport) -> Result<(), Error> {
+        match self.engine_for(params, viewport) {
+            Engine::Perturbation if !perturbable(params) => {
+                Err(Error::Invalid("the perturbation engine only renders the Mandelbrot set".to_string()))
+            }
+            engine @ (Engine::Precise | Engine::Perturbation) if self.aa > 1 => {
+                Err(Error::Invalid(format!("--aa is only supported with the double engine, and this view needs the {} engine",
+                                           engine.to_possible_value().unwrap().get_name())))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_args_viewport() {
+    let args = Args::parse_from(["mandelbrot", "-o", "out.png", "-s", "400x200",
+                                 "--center", "1,1", "--zoom", "2"]);
+    let viewport = args.viewport().unwrap();
+    assert_eq!((viewport.center, viewport.width, viewport.height), (Complex { re: 1.0, im: 1.0 }, 4.0, 2.0));
+
+    let args = Args::parse_from(["mandelbrot", "-o", "out.png", "-s", "400x200",
+                                 "--center", "1,1", "--width", "1", "--rotate", "90"]);
+    let viewport = args.viewport().unwrap();
+    assert_eq!((viewport.width, viewport.height), (1.0, 0.5));
+    assert_eq!(viewport.rotation, std::f64::consts::FRAC_PI_2);
+
+    let args = Args::parse_from(["mandelbrot", "-o", "out.png", "-s", "200x200",
+                                 "--upper-left", "-1,1", "--lower-right", "1,0"]);
+    let viewport = args.viewport().unwrap();
+    assert_eq!((viewport.center, viewport.width, viewport.height), (Complex { re: 0.0, im: 0.5 }, 2.0, 2.0));
+
+    let args = Args::parse_from(["mandelbrot", "-o", "out.png",
+                                 "--upper-left", "1,-1", "--lower-right", "-1,1"]);
+    assert!(args.validate().is_err());
+
+    let args = Args::parse_from(["mandelbrot", "-o", "out.png", "--center",
+                                 "-1.00000000000000000001,0", "--zoom", "1e20"]);
+    let viewport = args.viewport().unwrap();
+    assert_eq!(viewport.center, Complex { re: -1.0, im: 0.0 });
+    let center = args.precise_center(&viewport, 100);
+    assert_ne!(center, FixedComplex::from_complex(viewport.center, 100));
+
+    let args = Args::parse_from(["mandelbrot", "-o", "out.png", "--zoom", "0"]);
+    assert!(args.validate().is_err());
+    let args = Args::parse_from(["mandelbrot", "-o", "out.png", "--width=-2"]);
+    assert!(args.validate().is_err());
+    for point in [&["--center=inf,0"][..], &["--center=0,nan"], &["--center=1e999999,0"],
+                  &["--upper-left=-inf,1", "--lower-right=1,-1"], &["--frames=2", "--final-zoom=2", "--to=0,-inf"]] {
+        let args = Args::parse_from(["mandelbrot", "-o", "out.png"].iter().chain(point));
+        assert!(matches!(args.validate(), Err(Error::Viewport(_))), "{:?}", point);
+    }
+}
+
+#[test]
+fn test_args_rejects_bad_values() {
+    assert!(Args::try_parse_from(["mandelbrot"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "-s", "0x10"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--fractal", "nope"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--upper-left", "0,0"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--center", "0,0",
+                                  "--upper-left", "0,0", "--lower-right", "1,-1"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--zoom", "2", "--width", "1"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--center", "0"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--engine", "quad"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--frames", "10"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--final-zoom", "10"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--frames", "10", "--final-zoom", "10",
+                                  "--load-field", "a.fld"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--frames", "10", "--final-zoom", "10",
+                                  "--scene", "a.toml"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--animation", "a.gif"]).is_err());
+
+    let args = Args::parse_from(["mandelbrot", "--job", "batch.toml"]);
+    assert!(args.validate().is_err());
+
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--depth", "12"]).is_err());
+    assert!(Args::try_parse_from(["mandelbrot", "-o", "a.png", "--format", "bmp"]).is_err());
+    for (output, format, valid) in [("a.png", None, true), ("a.jpg", None, false), ("a", Some("tiff"), true),
+                                    ("a.png", Some("webp"), false)] {
+        let mut argv = vec!["mandelbrot", "-o", output, "--depth", "16"];
+        argv.extend(format.iter().flat_map(|format| ["--format", format]));
+        assert_eq!(Args::parse_from(argv).validate().is_ok(), valid);
+    }
+    let args = Args::parse_from(["mandelbrot", "-o", "a.pfm", "--aa", "2"]);
+    assert_eq!(args.format(), Format::Pfm);
+    assert!(args.validate().is_err());
+
+    // Supersampling is checked against the engine auto picks, too.
+    for (zoom, valid) in [("10", true), ("1e20", false)] {
+        assert_eq!(Args::parse_from(["mandelbrot", "-o", "a.png", "--aa", "2", "--zoom", zoom]).validate().is_ok(), valid);
+    }
+    assert!(Args::parse_from(["mandelbrot", "-o", "a.png", "--aa", "2", "--engine", "precise"]).validate().is_err());
+    for offset in ["--offset=NaN", "--offset=inf", "--offset=-inf"] {
+        assert!(matches!(Args::parse_from(["mandelbrot", "-o", "a.png", offset]).validate(), Err(Error::Invalid(_))));
+    }
+    assert!(Args::parse_from(["mandelbrot", "-o", "a.png", "--offset", "-0.25"]).validate().is_ok());
+
+    // Perturbation is picked, or allowed, per set rendered, as scene frames
+    // can change the Julia constant.
+    let args = Args::parse_from(["mandelbrot", "-o", "a.png", "--zoom", "1e20"]);
+    let viewport = args.viewport().unwrap();
+    let julia = Params { mode: Mode::Julia(Complex { re: -0.8, im: 0.156 }), ..args.params() };
+    assert_eq!(args.engine_for(&args.params(), &viewport), Engine::Perturbation);
+    assert_eq!(args.engine_for(&julia, &viewport), Engine::Precise);
+    let args = Args::parse_from(["mandelbrot", "-o", "a.png", "--zoom", "1e20", "--engine", "perturbation"]);
+    assert!(args.check_engine(&args.params(), &viewport).is_ok());
+    assert!(matches!(args.check_engine(&julia, &viewport), Err(Error::Invalid(_))));
+}
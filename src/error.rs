@@ -0,0 +1,70 @@
+//! The error type shared by the whole crate.
+
+use std::fmt;
+use std::io;
+
+/// Something that went wrong reading, rendering or writing.
+#[derive(Debug)]
+pub enum Error {
+    /// Text or data that doesn't parse: a name, number or point, or the
+    /// contents of a palette, scene or field file.
+    Parse(String),
+    /// A view that can't be rendered, such as one with a zoom of zero or
+    /// corners the wrong way round.
+    Viewport(String),
+    /// Values that can't be used, or can't be used together, such as a
+    /// fractal the chosen engine can't iterate.
+    Invalid(String),
+    /// Reading or writing a file failed.
+    Io {
+        /// What was being done, such as `reading palette sunset.txt`.
+        context: String,
+        /// Why it failed.
+        source: io::Error,
+    },
+    /// An image or animation couldn't be encoded or decoded.
+    Encoding(String),
+}
+
+impl Error {
+    /// An [`Error::Io`] that happened while doing `context`.
+    pub fn io(context: impl Into<String>, source: io::Error) -> Error {
+        Error::Io { context: context.into(), source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(message) | Error::Viewport(message) | Error::Invalid(message) | Error::Encoding(message) => {
+                f.write_str(message)
+            }
+            Error::Io { context, source } if context.is_empty() => write!(f, "{}", source),
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Error {
+        Error::io("", source)
+    }
+}
+
+#[test]
+fn test_error_display() {
+    let missing = || io::Error::new(io::ErrorKind::NotFound, "no such file");
+    assert_eq!(Error::io("reading a.fld", missing()).to_string(), "reading a.fld: no such file");
+    assert_eq!(Error::from(missing()).to_string(), "no such file");
+    assert_eq!(Error::Parse("bad point".to_string()).to_string(), "bad point");
+    assert!(std::error::Error::source(&Error::from(missing())).is_some());
+}
@@ -0,0 +1,158 @@
+// The explorer: a window onto the view that the mouse and keyboard move
+// around.
+//
+//     drag        pan
+//     wheel       zoom in or out about the pointer
+//     + -         zoom in or out about the middle
+//     arrows      pan by an eighth of the window
+//     [ ]         halve or double the iteration limit
+//     p f         next palette, next fractal
+//     r           back to the starting view
+//     s           render the view at full size
+//     q, Esc      quit
+
+use crate::cli::Args;
+use crate::view::{View, FRACTALS};
+use clap::ArgMatches;
+use mandelbrot::palette::BUILTIN_NAMES;
+use mandelbrot::Error;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::time::{Duration, Instant};
+
+// How many window pixels across each computed pixel covers in each pass of
+// progressive refinement, coarsest first.
+const SCALES: &[usize] = &[8, 4, 2, 1];
+
+// How long the view has to stay still before passes finer than the first.
+const SETTLE: Duration = Duration::from_millis(150);
+
+// The largest window; larger --size values are shrunk to fit.
+const MAX_WINDOW: (usize, usize) = (1280, 960);
+
+// Magnification for one notch of the mouse wheel.
+const WHEEL_ZOOM: f64 = 1.25;
+
+// Where exports go without --output, numbered.
+const DEFAULT_OUTPUT: &str = "explore.png";
+
+// The window size for images of `size`: the same, or shrunk to fit in
+// MAX_WINDOW.
+fn window_size(size: (usize, usize)) -> (usize, usize) {
+    let shrink = (size.0 as f64 / MAX_WINDOW.0 as f64).max(size.1 as f64 / MAX_WINDOW.1 as f64).max(1.0);
+    ((size.0 as f64 / shrink).round().max(1.0) as usize, (size.1 as f64 / shrink).round().max(1.0) as usize)
+}
+
+// Renders `view` into `buffer`, the window's pixels as 0RGB, with each
+// pixel computed covering `scale` pixels across.
+fn draw(view: &mut View, buffer: &mut [u32], scale: usize) -> Result<(), Error> {
+    let bounds = view.bounds();
+    let image = view.render((bounds.0.div_ceil(scale), bounds.1.div_ceil(scale)))?;
+    for (y, row) in buffer.chunks_exact_mut(bounds.0).enumerate() {
+        let Some(source) = image.row(y / scale) else { break };
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let [r, g, b] = source[x / scale];
+            *pixel = u32::from_be_bytes([0, r, g, b]);
+        }
+    }
+    Ok(())
+}
+
+// Opens the explorer on the view `matches` describes, returning when the
+// window closes.
+pub fn run(args: &Args, matches: &ArgMatches) -> Result<(), Error> {
+    let bounds = window_size(args.size);
+    let mut view = View::new(matches, bounds, DEFAULT_OUTPUT)?;
+    let mut window = Window::new("mandelbrot", bounds.0, bounds.1, WindowOptions::default())
+        .map_err(|err| Error::Invalid(format!("can't open a window: {}", err)))?;
+    window.set_target_fps(60);
+    let mut buffer = vec![0; bounds.0 * bounds.1];
+    let step = (bounds.0 as f64 / 8.0, bounds.1 as f64 / 8.0);
+    let mut pass = 0;
+    let mut moved = Instant::now();
+    let mut drag: Option<(f64, f64)> = None;
+
+    while window.is_open() {
+        let mut changed = false;
+        for key in window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Escape | Key::Q => return Ok(()),
+                Key::Left => view.pan((step.0, 0.0)),
+                Key::Right => view.pan((-step.0, 0.0)),
+                Key::Up => view.pan((0.0, step.1)),
+                Key::Down => view.pan((0.0, -step.1)),
+                Key::Equal | Key::NumPadPlus => view.zoom_middle(2.0),
+                Key::Minus | Key::NumPadMinus => view.zoom_middle(0.5),
+                Key::LeftBracket => view.scale_limit(0.5)?,
+                Key::RightBracket => view.scale_limit(2.0)?,
+                Key::P => view.cycle("palette", BUILTIN_NAMES),
+                Key::F => view.cycle("fractal", FRACTALS),
+                Key::R => view.reset()?,
+                Key::S => {
+                    match view.export() {
+                        Ok(filename) => eprintln!("wrote {}", filename),
+                        Err(err) => eprintln!("error: {}", err),
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+            changed = true;
+        }
+
+        let pointer = window.get_mouse_pos(MouseMode::Clamp).map(|(x, y)| (x as f64, y as f64));
+        if let (Some((_, wheel)), Some(position)) = (window.get_scroll_wheel(), pointer) {
+            if wheel != 0.0 {
+                view.zoom(position, if wheel > 0.0 { WHEEL_ZOOM } else { 1.0 / WHEEL_ZOOM });
+                changed = true;
+            }
+        }
+        let dragging = window.get_mouse_down(MouseButton::Left);
+        if let (true, Some(from), Some(to)) = (dragging, drag, pointer) {
+            if from != to {
+                view.pan((to.0 - from.0, to.1 - from.1));
+                changed = true;
+            }
+        }
+        drag = if dragging { pointer } else { None };
+
+        if changed {
+            pass = 0;
+            moved = Instant::now();
+        }
+        // The coarsest pass keeps up with the pointer; finer ones wait for
+        // the view to settle, one per frame so input is never held up long.
+        if pass < SCALES.len() && (pass == 0 || moved.elapsed() >= SETTLE) {
+            match draw(&mut view, &mut buffer, SCALES[pass]) {
+                Ok(()) => pass += 1,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    pass = SCALES.len();
+                }
+            }
+            window.set_title(&view.status());
+        }
+        window.update_with_buffer(&buffer, bounds.0, bounds.1)
+            .map_err(|err| Error::Invalid(format!("can't draw the window: {}", err)))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_draw() {
+    use clap::CommandFactory;
+
+    assert_eq!(window_size((4000, 3000)), (1280, 960));
+    assert_eq!(window_size((80, 40)), (80, 40));
+    let matches = Args::command().get_matches_from(["mandelbrot", "--explore", "-s", "80x40", "--upper-left=-2,1",
+                                                    "--lower-right=2,-1"]);
+    let mut view = View::new(&matches, window_size((80, 40)), DEFAULT_OUTPUT).unwrap();
+    assert_eq!(view.option("output"), DEFAULT_OUTPUT);
+
+    let mut coarse = vec![0; 80 * 40];
+    draw(&mut view, &mut coarse, 8).unwrap();
+    assert_eq!(coarse[0], coarse[7]);
+    assert_eq!(coarse[0], coarse[80 * 7]);
+    let mut fine = vec![0; 80 * 40];
+    draw(&mut view, &mut fine, 1).unwrap();
+    assert_ne!(coarse, fine);
+}
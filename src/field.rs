@@ -0,0 +1,180 @@
+//! The raw iteration results of a render, and their on-disk format.
+
+use crate::error::Error;
+use crate::pixels::Image;
+use num::Complex;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+/// How a point escaped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Escape {
+    /// Iterations taken before |z| passed the escape radius.
+    pub count: usize,
+    /// Continuous iteration count: equal to `count` when z escapes exactly on
+    /// the radius, and falling towards `count - 1` the further past it lands.
+    pub smooth: f64,
+    /// The first z outside the escape radius.
+    pub z: Complex<f64>,
+}
+
+/// The raw result of iterating every pixel of an image, before coloring.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// The iteration limit the field was computed with.
+    pub limit: usize,
+    /// One sample per pixel; `None` marks points that never escaped.
+    pub samples: Image<Option<Escape>>,
+}
+
+const MAGIC: &[u8; 8] = b"TWODFLD\x01";
+
+// The bytes before the first record: the magic, width, height and limit.
+const HEADER_LEN: u64 = 32;
+
+// The most samples reserved before reading them, so that a corrupt header
+// can't ask for more memory than the file's records fill.
+const MAX_RESERVED: usize = 1 << 20;
+
+fn invalid_data(message: &str) -> Error {
+    Error::Parse(message.to_string())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    read_u64(reader).map(f64::from_bits)
+}
+
+impl Field {
+    /// A field of `bounds` pixels with every sample marked as interior.
+    /// Fails if there are more than a `usize` can count.
+    pub fn new(bounds: (usize, usize), limit: usize) -> Result<Field, Error> {
+        Ok(Field { limit, samples: Image::new(bounds)? })
+    }
+
+    /// Width and height in pixels.
+    pub fn bounds(&self) -> (usize, usize) {
+        self.samples.bounds()
+    }
+
+    /// Serializes the field.
+    ///
+    /// Layout, all little-endian: the magic bytes, then width, height and
+    /// limit as u64, then one record per sample in row-major order. A record
+    /// is a tag byte, 0 for interior points; escaped points follow the tag
+    /// with count as u64 and smooth, z.re, z.im as f64.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_all(MAGIC)?;
+        for n in [self.samples.width(), self.samples.height(), self.limit] {
+            writer.write_all(&(n as u64).to_le_bytes())?;
+        }
+        for sample in &self.samples {
+            match sample {
+                None => writer.write_all(&[0])?,
+                Some(escape) => {
+                    writer.write_all(&[1])?;
+                    writer.write_all(&(escape.count as u64).to_le_bytes())?;
+                    for x in [escape.smooth, escape.z.re, escape.z.im] {
+                        writer.write_all(&x.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserializes a field written by [`Field::write`].
+    pub fn read<R: Read>(reader: &mut R) -> Result<Field, Error> {
+        Field::read_sized(reader, None)
+    }
+
+    // Reads a field from `reader`, which holds `available` bytes if known.
+    fn read_sized<R: Read>(reader: &mut R, available: Option<u64>) -> Result<Field, Error> {
+        Field::read_records(reader, available).map_err(|err| match err {
+            Error::Io { source, .. } if source.kind() == io::ErrorKind::UnexpectedEof => {
+                invalid_data("field file ends early")
+            }
+            err => err,
+        })
+    }
+
+    fn read_records<R: Read>(reader: &mut R, available: Option<u64>) -> Result<Field, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not an iteration field file"));
+        }
+        let width = read_u64(reader)? as usize;
+        let height = read_u64(reader)? as usize;
+        let limit = read_u64(reader)? as usize;
+        let len = width.checked_mul(height)
+            .ok_or_else(|| invalid_data("field dimensions overflow"))?;
+        // Every record takes at least its tag byte.
+        if available.is_some_and(|available| len as u64 > available.saturating_sub(HEADER_LEN)) {
+            return Err(invalid_data("field file ends early"));
+        }
+
+        let mut samples = Vec::with_capacity(len.min(MAX_RESERVED));
+        for _ in 0..len {
+            let mut tag = [0];
+            reader.read_exact(&mut tag)?;
+            samples.push(match tag[0] {
+                0 => None,
+                1 => Some(Escape {
+                    count: read_u64(reader)? as usize,
+                    smooth: read_f64(reader)?,
+                    z: Complex { re: read_f64(reader)?, im: read_f64(reader)? },
+                }),
+                _ => return Err(invalid_data("bad sample tag in field file")),
+            });
+        }
+        Ok(Field { limit, samples: Image::from_pixels((width, height), samples)? })
+    }
+
+    /// Writes the field to a file.
+    pub fn save(&self, filename: &str) -> Result<(), Error> {
+        let context = || format!("writing field file {}", filename);
+        let mut writer = BufWriter::new(File::create(filename).map_err(|err| Error::io(context(), err))?);
+        self.write(&mut writer).and_then(|()| Ok(writer.flush()?)).map_err(|err| match err {
+            Error::Io { source, .. } => Error::io(context(), source),
+            err => err,
+        })
+    }
+
+    /// Reads a field saved with [`Field::save`].
+    pub fn load(filename: &str) -> Result<Field, Error> {
+        let context = || format!("reading field file {}", filename);
+        let file = File::open(filename).map_err(|err| Error::io(context(), err))?;
+        let file_len = file.metadata().map_err(|err| Error::io(context(), err))?.len();
+        Field::read_sized(&mut BufReader::new(file), Some(file_len)).map_err(|err| match err {
+            Error::Io { source, .. } => Error::io(context(), source),
+            Error::Parse(message) => Error::Parse(format!("{}: {}", filename, message)),
+            err => err,
+        })
+    }
+}
+
+#[test]
+fn test_field_round_trip() {
+    let mut field = Field::new((2, 2), 1000).unwrap();
+    field.samples[1] = Some(Escape { count: 7, smooth: 6.25, z: Complex { re: -300.0, im: 0.5 } });
+    field.samples[2] = Some(Escape { count: 0, smooth: 0.0, z: Complex { re: 3.0, im: 0.0 } });
+
+    let mut bytes = Vec::new();
+    field.write(&mut bytes).unwrap();
+    assert_eq!(Field::read(&mut bytes.as_slice()).unwrap(), field);
+
+    assert!(matches!(Field::read(&mut &bytes[..bytes.len() - 1]), Err(Error::Parse(_))));
+    let mut huge = bytes.clone();
+    huge[8..24].copy_from_slice(&[(1u64 << 32).to_le_bytes(), (1u64 << 24).to_le_bytes()].concat());
+    assert!(matches!(Field::read(&mut huge.as_slice()), Err(Error::Parse(_))));
+    assert!(matches!(Field::read_sized(&mut huge.as_slice(), Some(huge.len() as u64)), Err(Error::Parse(_))));
+    bytes[0] = b'X';
+    assert!(matches!(Field::read(&mut bytes.as_slice()), Err(Error::Parse(_))));
+    assert!(matches!(Field::load("no-such-dir/field.fld"), Err(Error::Io { .. })));
+}
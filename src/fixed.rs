@@ -0,0 +1,268 @@
+//! Arbitrary-precision fixed-point arithmetic for deep zooms.
+//!
+//! A [`Fixed`] is a big integer scaled by a power of two chosen per render,
+//! so every value in an iteration carries the same number of fraction bits.
+//! That is all escape-time iteration needs: no value grows much past the
+//! escape radius, and precision is set by how small a pixel is.
+
+use num::bigint::Sign;
+use num::traits::{Float, ToPrimitive, Zero};
+use num::{BigInt, Complex};
+use std::ops::{Add, Mul, Neg, Sub};
+
+// The most digits before the decimal point [`Fixed::parse`] accepts: enough
+// for any finite `f64`.
+const MAX_DIGITS: i64 = 309;
+
+/// The value `mantissa / 2^bits`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fixed {
+    mantissa: BigInt,
+    bits: u32,
+}
+
+impl Fixed {
+    /// Zero, with `bits` fraction bits.
+    pub fn zero(bits: u32) -> Fixed {
+        Fixed { mantissa: BigInt::zero(), bits }
+    }
+
+    /// The number of fraction bits.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// `x` rounded toward negative infinity to `bits` fraction bits. Exact
+    /// whenever `x` has no set bits below `2^-bits`.
+    pub fn from_f64(x: f64, bits: u32) -> Fixed {
+        let (mantissa, exponent, sign) = x.integer_decode();
+        let mut value = BigInt::from(mantissa) * sign;
+        let shift = exponent as i64 + bits as i64;
+        if shift >= 0 {
+            value <<= shift as usize;
+        } else {
+            value >>= (-shift) as usize;
+        }
+        Fixed { mantissa: value, bits }
+    }
+
+    /// Parses a decimal number such as `-0.7436438870371587047521915` or
+    /// `1.5e-3` to `bits` fraction bits, keeping every digit given. Values too
+    /// large for an `f64` are rejected; values too small for `bits` parse as
+    /// zero.
+    pub fn parse(s: &str, bits: u32) -> Option<Fixed> {
+        let s = s.trim();
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (digits, exponent) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], s[i + 1..].parse::<i64>().ok()?),
+            None => (s, 0),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        if !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let significant = format!("{}{}", whole, fraction).trim_start_matches('0').len() as i64;
+        let scale = exponent.saturating_sub(fraction.len() as i64);
+        if significant == 0 || scale.saturating_add(significant) < -(bits as i64 / 3 + 1) {
+            // Below 10^-(bits/3 + 1), and so below 2^-bits.
+            return Some(Fixed::zero(bits));
+        }
+        if scale.saturating_add(significant) > MAX_DIGITS {
+            return None;
+        }
+
+        let mut numerator: BigInt = format!("0{}{}", whole, fraction).parse().ok()?;
+        numerator <<= bits as usize;
+        let ten = BigInt::from(10);
+        let mantissa = if scale >= 0 {
+            numerator * num::pow(ten, scale as usize)
+        } else {
+            numerator / num::pow(ten, (-scale) as usize)
+        };
+        let mantissa = if negative { -mantissa } else { mantissa };
+        Some(Fixed { mantissa, bits })
+    }
+
+    /// The nearest `f64`, or thereabouts: only the leading 64 bits of the
+    /// mantissa are kept.
+    pub fn to_f64(&self) -> f64 {
+        let length = self.mantissa.bits() as i64;
+        let shift = (length - 64).max(0);
+        let top = (&self.mantissa >> shift as usize).to_f64().unwrap_or(0.0);
+        let exponent = shift - self.bits as i64;
+        // Scale in two steps so tiny values don't underflow halfway.
+        let half = (exponent / 2) as i32;
+        top * 2.0f64.powi(half) * 2.0f64.powi(exponent as i32 - half)
+    }
+
+    /// The absolute value.
+    pub fn abs(&self) -> Fixed {
+        match self.mantissa.sign() {
+            Sign::Minus => -self,
+            _ => self.clone(),
+        }
+    }
+}
+
+impl Add for &Fixed {
+    type Output = Fixed;
+    fn add(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { mantissa: &self.mantissa + &other.mantissa, bits: self.bits }
+    }
+}
+
+impl Sub for &Fixed {
+    type Output = Fixed;
+    fn sub(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { mantissa: &self.mantissa - &other.mantissa, bits: self.bits }
+    }
+}
+
+impl Mul for &Fixed {
+    type Output = Fixed;
+    fn mul(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { mantissa: (&self.mantissa * &other.mantissa) >> self.bits as usize, bits: self.bits }
+    }
+}
+
+impl Neg for &Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed { mantissa: -&self.mantissa, bits: self.bits }
+    }
+}
+
+#[test]
+fn test_fixed_arithmetic() {
+    let a = Fixed::from_f64(1.5, 64);
+    let b = Fixed::from_f64(-0.25, 64);
+    assert_eq!((&a + &b).to_f64(), 1.25);
+    assert_eq!((&a - &b).to_f64(), 1.75);
+    assert_eq!((&a * &b).to_f64(), -0.375);
+    assert_eq!(b.abs().to_f64(), 0.25);
+    assert_eq!(Fixed::from_f64(1e-300, 1200).to_f64(), 1e-300);
+    assert_eq!(Fixed::zero(10).to_f64(), 0.0);
+}
+
+#[test]
+fn test_fixed_parse() {
+    assert_eq!(Fixed::parse("-0.75", 32), Some(Fixed::from_f64(-0.75, 32)));
+    assert_eq!(Fixed::parse("1.5e-3", 64).unwrap().to_f64(), 1.5e-3);
+    assert_eq!(Fixed::parse("+2", 8), Some(Fixed::from_f64(2.0, 8)));
+    assert_eq!(Fixed::parse(".5", 8), Some(Fixed::from_f64(0.5, 8)));
+    assert_eq!(Fixed::parse("", 8), None);
+    assert_eq!(Fixed::parse("1.2.3", 8), None);
+    assert_eq!(Fixed::parse("1e", 8), None);
+    assert_eq!(Fixed::parse("-1e-99999999", 64), Some(Fixed::zero(64)));
+    assert_eq!(Fixed::parse("1e-9223372036854775808", 64), Some(Fixed::zero(64)));
+    assert_eq!(Fixed::parse("0e99999999", 64), Some(Fixed::zero(64)));
+    assert_eq!(Fixed::parse("1e99999999", 64), None);
+    assert_eq!(Fixed::parse("1e308", 8).unwrap().to_f64(), 1e308);
+    assert_ne!(Fixed::parse("1e-19", 64), Some(Fixed::zero(64)));
+    assert_eq!(Fixed::parse("1e-20", 64), Some(Fixed::zero(64)));
+
+    // Digits past f64's precision survive: these differ only at 1e-40.
+    let bits = 200;
+    let a = Fixed::parse("0.1000000000000000000000000000000000000001", bits).unwrap();
+    let b = Fixed::parse("0.1", bits).unwrap();
+    let difference = (&a - &b).to_f64();
+    assert!((difference - 1e-40).abs() < 1e-50);
+}
+
+/// A complex number with [`Fixed`] parts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixedComplex {
+    /// Real part.
+    pub re: Fixed,
+    /// Imaginary part.
+    pub im: Fixed,
+}
+
+impl FixedComplex {
+    /// Zero, with `bits` fraction bits.
+    pub fn zero(bits: u32) -> FixedComplex {
+        FixedComplex { re: Fixed::zero(bits), im: Fixed::zero(bits) }
+    }
+
+    /// `z` to `bits` fraction bits.
+    pub fn from_complex(z: Complex<f64>, bits: u32) -> FixedComplex {
+        FixedComplex { re: Fixed::from_f64(z.re, bits), im: Fixed::from_f64(z.im, bits) }
+    }
+
+    /// Parses `RE,IM` with decimal parts of any length.
+    pub fn parse(s: &str, bits: u32) -> Option<FixedComplex> {
+        let (re, im) = s.split_once(',')?;
+        Some(FixedComplex { re: Fixed::parse(re, bits)?, im: Fixed::parse(im, bits)? })
+    }
+
+    /// The number of fraction bits.
+    pub fn bits(&self) -> u32 {
+        self.re.bits()
+    }
+
+    /// The nearest `Complex<f64>`.
+    pub fn to_complex(&self) -> Complex<f64> {
+        Complex { re: self.re.to_f64(), im: self.im.to_f64() }
+    }
+
+    /// `self + other`
+    pub fn add(&self, other: &FixedComplex) -> FixedComplex {
+        FixedComplex { re: &self.re + &other.re, im: &self.im + &other.im }
+    }
+
+    /// `self - other`
+    pub fn sub(&self, other: &FixedComplex) -> FixedComplex {
+        FixedComplex { re: &self.re - &other.re, im: &self.im - &other.im }
+    }
+
+    /// `self * other`
+    pub fn mul(&self, other: &FixedComplex) -> FixedComplex {
+        FixedComplex {
+            re: &(&self.re * &other.re) - &(&self.im * &other.im),
+            im: &(&self.re * &other.im) + &(&self.im * &other.re),
+        }
+    }
+
+    /// `self * self`, with one multiplication fewer than `mul`.
+    pub fn square(&self) -> FixedComplex {
+        let cross = &self.re * &self.im;
+        FixedComplex {
+            re: &(&self.re * &self.re) - &(&self.im * &self.im),
+            im: &cross + &cross,
+        }
+    }
+
+    /// The complex conjugate.
+    pub fn conj(&self) -> FixedComplex {
+        FixedComplex { re: self.re.clone(), im: -&self.im }
+    }
+
+    /// |self|^2, as an `f64`.
+    pub fn norm_sqr(&self) -> f64 {
+        self.to_complex().norm_sqr()
+    }
+}
+
+#[test]
+fn test_fixed_complex() {
+    let z = Complex { re: 1.0, im: -2.0 };
+    let c = Complex { re: 0.5, im: 0.25 };
+    let (zf, cf) = (FixedComplex::from_complex(z, 64), FixedComplex::from_complex(c, 64));
+    assert_eq!(zf.square().add(&cf).to_complex(), z * z + c);
+    assert_eq!(zf.sub(&cf).to_complex(), z - c);
+    assert_eq!(zf.mul(&cf).to_complex(), z * c);
+    assert_eq!(zf.conj().to_complex(), z.conj());
+    assert_eq!(zf.norm_sqr(), 5.0);
+    assert_eq!(FixedComplex::parse("1,-2", 64), Some(zf));
+    assert_eq!(FixedComplex::parse("1", 64), None);
+}
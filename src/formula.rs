@@ -0,0 +1,313 @@
+//! The maps iterated by the renderer.
+
+use crate::error::Error;
+use crate::fixed::FixedComplex;
+use num::Complex;
+use std::str::FromStr;
+
+/// An escape-time map `z -> f(z, c)`.
+pub trait Formula {
+    /// One iteration of the map, taking `z` to its successor.
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64>;
+
+    /// How fast |z| grows once it is large: |step(z)| ~ |z|^degree. Used to
+    /// turn integer escape counts into continuous ones.
+    fn degree(&self) -> f64 {
+        2.0
+    }
+
+    /// Whether `c` is known to lie in the set without iterating, for orbits
+    /// starting at zero. Only needs to be right when it says yes.
+    fn known_interior(&self, _c: Complex<f64>) -> bool {
+        false
+    }
+
+    /// Whether `step` is exactly `z * z + c`, which [`crate::simd`] can
+    /// iterate several points at a time.
+    fn is_quadratic(&self) -> bool {
+        false
+    }
+
+    /// `step` in arbitrary precision, for formulas that support it.
+    fn step_precise(&self, _z: &FixedComplex, _c: &FixedComplex) -> Option<FixedComplex> {
+        None
+    }
+}
+
+/// `z^2 + c`
+pub struct Mandelbrot;
+
+impl Formula for Mandelbrot {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        z * z + c
+    }
+
+    fn is_quadratic(&self) -> bool {
+        true
+    }
+
+    // The main cardioid and the period-2 bulb, in closed form.
+    fn known_interior(&self, c: Complex<f64>) -> bool {
+        let x = c.re - 0.25;
+        let q = x * x + c.im * c.im;
+        if q * (q + x) <= 0.25 * c.im * c.im {
+            return true;
+        }
+        (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 1.0 / 16.0
+    }
+
+    fn step_precise(&self, z: &FixedComplex, c: &FixedComplex) -> Option<FixedComplex> {
+        Some(z.square().add(c))
+    }
+}
+
+#[test]
+fn test_known_interior() {
+    for (re, im) in [(0.0, 0.0), (-0.5, 0.5), (0.2, 0.0), (-1.0, 0.0), (-1.2, 0.1)] {
+        assert!(Mandelbrot.known_interior(Complex { re, im }));
+    }
+    for (re, im) in [(0.3, 0.0), (-0.75, 0.2), (-1.3, 0.0), (-2.0, 0.0), (0.0, 1.0)] {
+        assert!(!Mandelbrot.known_interior(Complex { re, im }));
+    }
+    assert!(!BurningShip.known_interior(Complex { re: 0.0, im: 0.0 }));
+}
+
+/// The exponent of a [`Multibrot`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Power {
+    /// Computed by repeated multiplication.
+    Integer(i32),
+    /// Computed through polar form.
+    Real(f64),
+}
+
+/// `z^n + c`
+pub struct Multibrot {
+    /// The exponent `n`.
+    pub power: Power,
+}
+
+impl Formula for Multibrot {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match self.power {
+            Power::Integer(n) => z.powi(n) + c,
+            Power::Real(p) => z.powf(p) + c,
+        }
+    }
+
+    fn degree(&self) -> f64 {
+        match self.power {
+            Power::Integer(n) => n as f64,
+            Power::Real(p) => p,
+        }
+    }
+
+    fn step_precise(&self, z: &FixedComplex, c: &FixedComplex) -> Option<FixedComplex> {
+        let n = match self.power {
+            Power::Integer(n) if n >= 1 => n as u32,
+            _ => return None,
+        };
+        // Square-and-multiply over the bits of n.
+        let mut result: Option<FixedComplex> = None;
+        let mut square = z.clone();
+        let mut rest = n;
+        while rest > 0 {
+            if rest & 1 == 1 {
+                result = Some(match result {
+                    None => square.clone(),
+                    Some(r) => r.mul(&square),
+                });
+            }
+            rest >>= 1;
+            if rest > 0 {
+                square = square.square();
+            }
+        }
+        result.map(|power| power.add(c))
+    }
+}
+
+/// `(|re z| + i |im z|)^2 + c`
+pub struct BurningShip;
+
+impl Formula for BurningShip {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let z = Complex { re: z.re.abs(), im: z.im.abs() };
+        z * z + c
+    }
+
+    fn step_precise(&self, z: &FixedComplex, c: &FixedComplex) -> Option<FixedComplex> {
+        let z = FixedComplex { re: z.re.abs(), im: z.im.abs() };
+        Some(z.square().add(c))
+    }
+}
+
+/// `conj(z)^2 + c`, also known as the Mandelbar.
+pub struct Tricorn;
+
+impl Formula for Tricorn {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let z = z.conj();
+        z * z + c
+    }
+
+    fn step_precise(&self, z: &FixedComplex, c: &FixedComplex) -> Option<FixedComplex> {
+        Some(z.conj().square().add(c))
+    }
+}
+
+/// `|re z^2| + i im z^2 + c`
+pub struct Celtic;
+
+impl Formula for Celtic {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let square = z * z;
+        Complex { re: square.re.abs(), im: square.im } + c
+    }
+
+    fn step_precise(&self, z: &FixedComplex, c: &FixedComplex) -> Option<FixedComplex> {
+        let square = z.square();
+        Some(FixedComplex { re: square.re.abs(), im: square.im }.add(c))
+    }
+}
+
+#[test]
+fn test_formula_step() {
+    let z = Complex { re: 1.0, im: -2.0 };
+    let c = Complex { re: 0.5, im: 0.25 };
+    assert_eq!(Mandelbrot.step(z, c), Complex { re: -2.5, im: -3.75 });
+    assert_eq!(Multibrot { power: Power::Integer(2) }.step(z, c), Mandelbrot.step(z, c));
+    assert_eq!(Multibrot { power: Power::Integer(3) }.step(z, c), Complex { re: -10.5, im: 2.25 });
+    assert_eq!(BurningShip.step(z, c), Complex { re: -2.5, im: 4.25 });
+    assert_eq!(Tricorn.step(z, c), Complex { re: -2.5, im: 4.25 });
+    assert_eq!(Celtic.step(z, c), Complex { re: 3.5, im: -3.75 });
+
+    let real = Multibrot { power: Power::Real(2.0) }.step(z, c);
+    assert!((real - Mandelbrot.step(z, c)).norm() < 1e-12);
+}
+
+#[test]
+fn test_formula_step_precise() {
+    let z = Complex { re: 1.0, im: -2.0 };
+    let c = Complex { re: 0.5, im: 0.25 };
+    let (zf, cf) = (FixedComplex::from_complex(z, 64), FixedComplex::from_complex(c, 64));
+    let formulas: Vec<Fractal> = vec![
+        Fractal::Mandelbrot,
+        Fractal::Multibrot(Power::Integer(3)),
+        Fractal::Multibrot(Power::Integer(6)),
+        Fractal::BurningShip,
+        Fractal::Tricorn,
+        Fractal::Celtic,
+    ];
+    for formula in formulas {
+        assert_eq!(formula.step_precise(&zf, &cf).unwrap().to_complex(), formula.step(z, c));
+    }
+    assert_eq!(Fractal::Multibrot(Power::Real(2.5)).step_precise(&zf, &cf), None);
+    assert_eq!(Fractal::Multibrot(Power::Integer(-2)).step_precise(&zf, &cf), None);
+}
+
+/// The formulas selectable by name on the command line.
+///
+/// Parses from `mandelbrot`, `multibrot:N`, `burning-ship`, `tricorn` (or
+/// `mandelbar`) and `celtic`. Multibrot exponents must be greater than 1:
+/// smaller ones have no smooth count and render as nearly flat images.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fractal {
+    /// See [`Mandelbrot`].
+    Mandelbrot,
+    /// See [`Multibrot`].
+    Multibrot(Power),
+    /// See [`BurningShip`].
+    BurningShip,
+    /// See [`Tricorn`].
+    Tricorn,
+    /// See [`Celtic`].
+    Celtic,
+}
+
+impl Formula for Fractal {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.step(z, c),
+            Fractal::Multibrot(power) => Multibrot { power }.step(z, c),
+            Fractal::BurningShip => BurningShip.step(z, c),
+            Fractal::Tricorn => Tricorn.step(z, c),
+            Fractal::Celtic => Celtic.step(z, c),
+        }
+    }
+
+    fn degree(&self) -> f64 {
+        match *self {
+            Fractal::Multibrot(power) => Multibrot { power }.degree(),
+            _ => 2.0,
+        }
+    }
+
+    fn is_quadratic(&self) -> bool {
+        *self == Fractal::Mandelbrot
+    }
+
+    fn known_interior(&self, c: Complex<f64>) -> bool {
+        match *self {
+            Fractal::Mandelbrot | Fractal::Multibrot(Power::Integer(2)) => Mandelbrot.known_interior(c),
+            _ => false,
+        }
+    }
+
+    fn step_precise(&self, z: &FixedComplex, c: &FixedComplex) -> Option<FixedComplex> {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.step_precise(z, c),
+            Fractal::Multibrot(power) => Multibrot { power }.step_precise(z, c),
+            Fractal::BurningShip => BurningShip.step_precise(z, c),
+            Fractal::Tricorn => Tricorn.step_precise(z, c),
+            Fractal::Celtic => Celtic.step_precise(z, c),
+        }
+    }
+}
+
+impl FromStr for Fractal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Fractal, Error> {
+        match s {
+            "mandelbrot" => return Ok(Fractal::Mandelbrot),
+            "burning-ship" => return Ok(Fractal::BurningShip),
+            "tricorn" | "mandelbar" => return Ok(Fractal::Tricorn),
+            "celtic" => return Ok(Fractal::Celtic),
+            _ => {}
+        }
+
+        // Multibrot takes its exponent after a colon, e.g. `multibrot:3` or `multibrot:2.5`.
+        let exponent = s.strip_prefix("multibrot:")
+            .ok_or_else(|| Error::Parse(format!("unknown fractal '{}'", s)))?;
+        let power = match (i32::from_str(exponent), f64::from_str(exponent)) {
+            (Ok(n), _) => Power::Integer(n),
+            (_, Ok(p)) if p.is_finite() => Power::Real(p),
+            _ => return Err(Error::Parse(format!("invalid multibrot exponent '{}'", exponent))),
+        };
+        let too_small = match power {
+            Power::Integer(n) => n <= 1,
+            Power::Real(p) => p <= 1.0,
+        };
+        if too_small {
+            return Err(Error::Parse(format!("multibrot exponent must be greater than 1, not {}", exponent)));
+        }
+        Ok(Fractal::Multibrot(power))
+    }
+}
+
+#[test]
+fn test_parse_fractal() {
+    assert_eq!("mandelbrot".parse().ok(), Some(Fractal::Mandelbrot));
+    assert_eq!("mandelbar".parse().ok(), Some(Fractal::Tricorn));
+    assert_eq!("burning-ship".parse().ok(), Some(Fractal::BurningShip));
+    assert_eq!("multibrot:3".parse().ok(), Some(Fractal::Multibrot(Power::Integer(3))));
+    assert_eq!("multibrot:2.5".parse().ok(), Some(Fractal::Multibrot(Power::Real(2.5))));
+    assert!("multibrot:".parse::<Fractal>().is_err());
+    assert!("multibrot:inf".parse::<Fractal>().is_err());
+    for exponent in ["1", "1.0", "0.5", "0", "-2", "-2.5"] {
+        assert!(matches!(format!("multibrot:{}", exponent).parse::<Fractal>(), Err(Error::Parse(_))));
+    }
+    assert_eq!("multibrot:1.01".parse().ok(), Some(Fractal::Multibrot(Power::Real(1.01))));
+    assert!("buddhabrot".parse::<Fractal>().is_err());
+}
@@ -0,0 +1,286 @@
+// Render jobs: TOML or JSON files whose keys are the command line's long
+// options, so a job means exactly what the same options would on the command
+// line.
+//
+//     fractal = "burning-ship"
+//     center = "-1.75,-0.045"
+//     zoom = 40
+//     aa = 3
+//     output = "ship.png"
+//
+// A file with a `job` array of tables is a batch: each table is one job, and
+// the file's other keys are shared by all of them.
+//
+// Images carry their job too, in a PNG text chunk alongside a readable
+// summary, so one can be rendered again from the image alone.
+
+use crate::cli::Args;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory};
+use mandelbrot::{read_text, Error, Field, Viewport};
+use std::fs;
+use std::path::Path;
+use toml::{Table, Value};
+
+// The keyword of the PNG text chunk holding an image's job.
+const IMAGE_KEYWORD: &str = "Render job";
+
+// Options that change how a render runs but not what it draws, left out of
+// effective job files.
+const NOT_RECORDED: &[&str] = &["help", "version", "job", "rerender", "write_job", "threads", "progress", "stats",
+                                   "explore", "preview"];
+
+fn parse(path: &str, text: &str) -> Result<Table, Error> {
+    let is_json = Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    if is_json {
+        serde_json::from_str(text).map_err(|err| Error::Parse(err.to_string()))
+    } else {
+        toml::from_str(text).map_err(|err| Error::Parse(err.to_string().trim_end().to_string()))
+    }
+}
+
+// Turns one job's keys into command line arguments.
+pub fn to_args(job: &Table) -> Result<Vec<String>, Error> {
+    let mut args = Vec::new();
+    for (key, value) in job {
+        let option = key.replace('_', "-");
+        if option == "job" {
+            return Err(Error::Parse("jobs can't contain other jobs".to_string()));
+        }
+        match value {
+            Value::String(text) => args.push(format!("--{}={}", option, text)),
+            Value::Integer(number) => args.push(format!("--{}={}", option, number)),
+            Value::Float(number) => args.push(format!("--{}={}", option, number)),
+            Value::Boolean(true) => args.push(format!("--{}", option)),
+            Value::Boolean(false) => {}
+            _ => return Err(Error::Parse(format!("'{}' must be a string, number or boolean", key))),
+        }
+    }
+    Ok(args)
+}
+
+// Command line arguments for each job in `text`, read from `path`.
+fn jobs(path: &str, text: &str) -> Result<Vec<Vec<String>>, Error> {
+    let mut table = parse(path, text)?;
+    let batch = match table.remove("job") {
+        None => return Ok(vec![to_args(&table)?]),
+        Some(Value::Array(batch)) => batch,
+        Some(_) => return Err(Error::Parse("'job' must be an array of tables".to_string())),
+    };
+    batch.into_iter()
+        .map(|job| match job {
+            Value::Table(job) => {
+                let mut merged = table.clone();
+                merged.extend(job);
+                to_args(&merged)
+            }
+            _ => Err(Error::Parse("'job' must be an array of tables".to_string())),
+        })
+        .collect()
+}
+
+// Reads the job or batch file at `path`, returning each job's arguments.
+pub fn load(path: &str) -> Result<Vec<Vec<String>>, Error> {
+    let text = fs::read_to_string(path).map_err(|err| Error::io(format!("reading job file {}", path), err))?;
+    jobs(path, &text).map_err(|err| Error::Parse(format!("job file {}: {}", path, err)))
+}
+
+// Every option that decides what `matches` renders, defaults included, as a
+// job that reproduces it.
+pub fn effective(matches: &ArgMatches) -> Table {
+    let command = Args::command();
+    let given = |id: &str| matches.value_source(id).is_some_and(|source| source != ValueSource::DefaultValue);
+    let mut job = Table::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if NOT_RECORDED.contains(&id) {
+            continue;
+        }
+        // A default can't be given alongside options it conflicts with,
+        // such as --rotate with the corners.
+        if !given(id) && command.get_arg_conflicts_with(arg).iter().any(|other| given(other.get_id().as_str())) {
+            continue;
+        }
+        let (Some(long), Some(mut values)) = (arg.get_long(), matches.get_raw(id)) else {
+            continue;
+        };
+        let Some(raw) = values.next() else {
+            continue;
+        };
+        let text = raw.to_string_lossy().into_owned();
+        if arg.get_action().takes_values() {
+            job.insert(long.to_string(), Value::String(text));
+        } else if text == "true" {
+            job.insert(long.to_string(), Value::Boolean(true));
+        }
+    }
+    job
+}
+
+fn to_toml(job: &Table) -> Result<String, Error> {
+    toml::to_string(job).map_err(|err| Error::Encoding(format!("encoding render job: {}", err)))
+}
+
+// Writes `job` beside the image `output`, as `output` with `.toml` added.
+pub fn write(output: &str, job: &Table) -> Result<(), Error> {
+    let filename = format!("{}.toml", output);
+    fs::write(&filename, to_toml(job)?).map_err(|err| Error::io(format!("writing {}", filename), err))
+}
+
+// The text chunks describing an image of `viewport` rendered by `job` with
+// `limit` iterations.
+pub fn image_text(job: &Table, viewport: &Viewport, limit: usize) -> Result<Vec<(String, String)>, Error> {
+    let (upper_left, lower_right) = viewport.corners();
+    let option = |key: &str| job.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+    let mut text = vec![
+        ("Software".to_string(), format!("mandelbrot {}", env!("CARGO_PKG_VERSION"))),
+        ("Fractal".to_string(), option("fractal")),
+    ];
+    if job.contains_key("julia") {
+        text.push(("Julia".to_string(), option("julia")));
+    }
+    text.extend([
+        ("Upper left".to_string(), format!("{},{}", upper_left.re, upper_left.im)),
+        ("Lower right".to_string(), format!("{},{}", lower_right.re, lower_right.im)),
+        ("Rotation".to_string(), option("rotate")),
+        ("Iterations".to_string(), limit.to_string()),
+        ("Palette".to_string(), option("palette")),
+        (IMAGE_KEYWORD.to_string(), to_toml(job)?),
+    ]);
+    Ok(text)
+}
+
+// `job` with the size and iteration limit of the loaded `field` in place of
+// the command line's, which don't apply to it.
+pub fn for_field(job: &Table, field: &Field) -> Table {
+    let mut job = job.clone();
+    let (width, height) = field.bounds();
+    job.insert("size".to_string(), Value::String(format!("{}x{}", width, height)));
+    job.insert("max-iter".to_string(), Value::String(field.limit.to_string()));
+    job
+}
+
+// The arguments of the job stored in the PNG `path`, leaving out its output
+// so the new image doesn't overwrite it.
+pub fn from_image(path: &str) -> Result<Vec<String>, Error> {
+    let text = read_text(path)?;
+    let (_, job) = text.iter()
+        .find(|(keyword, _)| keyword == IMAGE_KEYWORD)
+        .ok_or_else(|| Error::Parse(format!("{} has no render job in it", path)))?;
+    let mut job: Table = toml::from_str(job)
+        .map_err(|err| Error::Parse(format!("render job in {}: {}", path, err)))?;
+    job.remove("output");
+    to_args(&job)
+}
+
+#[test]
+fn test_jobs() {
+    let batch = jobs("batch.toml", "
+        size = \"200x100\"
+        aa = 2
+        jitter = true
+        no_interior_checks = false
+
+        [[job]]
+        output = \"a.png\"
+        zoom = 2.5
+
+        [[job]]
+        output = \"b.png\"
+        size = \"300x200\"
+    ").unwrap();
+    assert_eq!(batch, [vec!["--aa=2", "--jitter", "--output=a.png", "--size=200x100", "--zoom=2.5"],
+                       vec!["--aa=2", "--jitter", "--output=b.png", "--size=300x200"]]);
+
+    let single = jobs("single.JSON", r#"{ "fractal": "tricorn", "max-iter": 500, "output": "t.png" }"#).unwrap();
+    assert_eq!(single, [vec!["--fractal=tricorn", "--max-iter=500", "--output=t.png"]]);
+
+    assert!(jobs("bad.toml", "center = [1, 2]").is_err());
+    assert!(jobs("bad.toml", "job = 3").is_err());
+    assert!(jobs("bad.toml", "[[job]]\njob = \"x\"").is_err());
+    assert!(jobs("bad.json", "size = \"10x10\"").is_err());
+}
+
+#[test]
+fn test_effective_job_round_trip() {
+    use clap::FromArgMatches;
+
+    let matches = Args::command().get_matches_from(["mandelbrot", "-o", "out.png", "--center=-0.75,0.1",
+                                                    "--zoom", "30", "--jitter", "--aa", "2", "-i", "auto",
+                                                    "--stats", "--write-job"]);
+    let job = effective(&matches);
+    assert_eq!(job.get("center"), Some(&Value::String("-0.75,0.1".to_string())));
+    assert_eq!(job.get("palette"), Some(&Value::String("classic".to_string())));
+    assert_eq!(job.get("jitter"), Some(&Value::Boolean(true)));
+    assert!(!["adaptive", "stats", "write-job", "upper-left"].iter().any(|key| job.contains_key(*key)));
+
+    let text = toml::to_string(&job).unwrap();
+    let args = std::iter::once("mandelbrot".to_string()).chain(jobs("out.png.toml", &text).unwrap().remove(0));
+    let again = Args::command().get_matches_from(args);
+
+    let (before, after) = (Args::from_arg_matches(&matches).unwrap(), Args::from_arg_matches(&again).unwrap());
+    assert_eq!(format!("{:?}", Args { stats: false, write_job: false, ..before }), format!("{:?}", after));
+
+    let corners = Args::command().get_matches_from(["mandelbrot", "-o", "out.png", "--upper-left=-2,1",
+                                                    "--lower-right=1,-1"]);
+    let job = effective(&corners);
+    assert!(job.contains_key("upper-left") && !job.contains_key("rotate"));
+    let args = std::iter::once("mandelbrot".to_string()).chain(to_args(&job).unwrap());
+    assert!(Args::command().try_get_matches_from(args).is_ok());
+}
+
+#[test]
+fn test_image_job() {
+    use mandelbrot::{write_image_with_text, Image};
+    use num::Complex;
+
+    let matches = Args::command().get_matches_from(["mandelbrot", "-o", "out.png", "--julia=-0.8,0.156", "-p", "magma"]);
+    let job = effective(&matches);
+    let viewport = Viewport::from_corners(Complex { re: -2.0, im: 1.5 }, Complex { re: 1.0, im: -0.5 });
+    let text = image_text(&job, &viewport, 300).unwrap();
+    let keywords: Vec<&str> = text.iter().map(|(keyword, _)| keyword.as_str()).collect();
+    assert_eq!(keywords, ["Software", "Fractal", "Julia", "Upper left", "Lower right", "Rotation", "Iterations",
+                          "Palette", IMAGE_KEYWORD]);
+    assert_eq!(text[3].1, "-2,1.5");
+    assert_eq!(text[6].1, "300");
+
+    let path = std::env::temp_dir().join("twod-test-image-job.png");
+    let filename = path.to_str().unwrap();
+    write_image_with_text(filename, &Image::new((1, 1)).unwrap(), &text).unwrap();
+    let args = from_image(filename).unwrap();
+    assert!(args.contains(&"--palette=magma".to_string()) && args.contains(&"--julia=-0.8,0.156".to_string()));
+    assert!(!args.iter().any(|arg| arg.starts_with("--output")));
+
+    mandelbrot::write_image(filename, &Image::new((1, 1)).unwrap()).unwrap();
+    assert!(from_image(filename).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_field_image_job() {
+    use clap::FromArgMatches;
+    use mandelbrot::{write_image_with_text, Image, Limit};
+
+    // A loaded field keeps its own size and limit, whatever the command line
+    // says, and so does the job that renders its image again.
+    let matches = Args::command().get_matches_from(["mandelbrot", "-o", "out.png", "--load-field=view.fld", "-s", "800x600",
+                                                    "-i", "auto", "--zoom", "4"]);
+    let field = Field::new((30, 20), 321).unwrap();
+    let job = for_field(&effective(&matches), &field);
+    let args = Args::from_arg_matches(&Args::command().get_matches_from(
+        std::iter::once("mandelbrot".to_string()).chain(to_args(&job).unwrap()))).unwrap();
+    let text = image_text(&job, &args.viewport().unwrap(), field.limit).unwrap();
+
+    let path = std::env::temp_dir().join("twod-test-field-image-job.png");
+    let filename = path.to_str().unwrap();
+    write_image_with_text(filename, &Image::new((1, 1)).unwrap(), &text).unwrap();
+    let again = Args::from_arg_matches(&Args::command().get_matches_from(
+        ["mandelbrot".to_string(), "-o".to_string(), "again.png".to_string()].into_iter().chain(from_image(filename).unwrap()))).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((again.size, again.max_iter), (field.bounds(), Limit::Fixed(321)));
+    assert_eq!(again.load_field.as_deref(), Some("view.fld"));
+    let (upper_left, _) = again.viewport().unwrap().corners();
+    assert_eq!(text[2], ("Upper left".to_string(), format!("{},{}", upper_left.re, upper_left.im)));
+    assert_eq!(text[5].1, "321");
+}
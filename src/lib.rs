@@ -0,0 +1,101 @@
+//! Escape-time fractal rendering.
+//!
+//! Rendering happens in two stages. [`render()`] iterates every pixel of a
+//! [`Viewport`] and records the raw results in a [`Field`]; [`colorize`] then
+//! maps the field through a [`ColorMap`] to an [`Image`] of RGB pixels, which
+//! [`write_image`] saves as a PNG. Keeping the stages apart means a field can
+//! be saved and recolored without iterating it again. For anti-aliased
+//! output, [`supersample`] takes the place of [`colorize`].
+//!
+//! ```no_run
+//! use mandelbrot::{colorize, render, write_image, ColorMap, Fractal, Mode, Palette, Params, Viewport};
+//! use num::Complex;
+//!
+//! let bounds = (800, 600);
+//! let viewport = Viewport::from_zoom(Complex { re: -0.5, im: 0.0 }, 1.0, 0.0).fit(bounds);
+//! let params = Params {
+//!     formula: Fractal::Mandelbrot,
+//!     mode: Mode::Mandelbrot,
+//!     radius: 256.0,
+//!     interior_checks: true,
+//! };
+//! let field = render(bounds, &viewport, &params, 500).unwrap();
+//! let pixels = colorize(&field, &ColorMap::new(Palette::builtin("classic").unwrap()));
+//! write_image("mandel.png", &pixels).unwrap();
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod animation;
+pub mod antialias;
+pub mod error;
+pub mod field;
+pub mod fixed;
+pub mod formula;
+pub mod output;
+pub mod palette;
+pub mod perturbation;
+pub mod pixels;
+pub mod precise;
+pub mod render;
+pub mod scene;
+pub mod simd;
+pub mod strategy;
+pub mod tile;
+pub mod viewport;
+
+pub use animation::{Easing, ZoomPath};
+pub use antialias::{supersample, supersample_as, Supersampling};
+pub use error::Error;
+pub use field::{Escape, Field};
+pub use formula::{Formula, Fractal};
+pub use output::{read_text, write_float_field, write_image, write_image_as, write_image_with_text, Animation, Format};
+pub use palette::{ColorMap, Palette};
+pub use pixels::{Channel, Image, Pixel};
+pub use scene::Scene;
+pub use render::{colorize, colorize_as, escape_time, render, render_with_stats, Limit, Mode, Params, Stats};
+pub use perturbation::render_perturbed;
+pub use precise::render_precise;
+pub use strategy::Strategy;
+pub use tile::{render_tiled, TileOrder, Tiled, Tiling};
+pub use viewport::{pixel_to_point, position_to_point, Viewport};
+
+use num::Complex;
+use std::str::FromStr;
+
+/// Parses a pair of values separated by `separator`, as in `"400x300"` or
+/// `"1.0,0.5"`.
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
+    match s.find(separator) {
+        None => None,
+        Some(index) => {
+            match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
+                (Ok(l), Ok(r)) => Some((l, r)),
+                _ => None
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_pair() {
+    assert_eq!(parse_pair::<i32>("",        ','), None);
+    assert_eq!(parse_pair::<i32>("10,",     ','), None);
+    assert_eq!(parse_pair::<i32>(",10",     ','), None);
+    assert_eq!(parse_pair::<i32>("10,20",   ','), Some((10, 20)));
+    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
+    assert_eq!(parse_pair::<f64>("0.5x",    'x'), None);
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+}
+
+/// Parses a complex number written as `RE,IM`.
+pub fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    parse_pair::<f64>(s, ',').map(|(re, im)| Complex { re, im })
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(parse_complex("1.25,-0.0625"),
+               Some(Complex { re: 1.25, im: -0.0625 }));
+    assert_eq!(parse_complex(",-0.0625"), None);
+}
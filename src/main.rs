@@ -1,145 +1,215 @@
-
+mod animate;
+mod cli;
+#[cfg(feature = "explore")]
+mod explore;
+mod job;
+mod preview;
+mod view;
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use cli::{clap_error, Args, Engine};
+use mandelbrot::fixed::FixedComplex;
+use mandelbrot::perturbation::render_perturbed_from;
+use mandelbrot::precise::precision_for;
+use mandelbrot::{colorize_as, render_perturbed, render_precise, render_tiled, supersample_as, write_float_field, write_image_as,
+                 Channel, ColorMap, Error, Field, Format, Image, Fractal, Limit, Params, Tiled, Viewport};
 use num::Complex;
-use std::{str::FromStr};
-use image::ColorType;
-use image::png::PNGEncoder;
-use std::fs::File;
-use std::env;
-use rand::distributions::{Normal, Distribution};
-use rayon::prelude::*;
-
+use std::ffi::OsString;
+use toml::Table;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A perturbation reference orbit shared between views, and the offset of the
+// current view's center from the orbit's starting point.
+struct Reference<'a> {
+    orbit: &'a [Complex<f64>],
+    offset: Complex<f64>,
+}
 
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
-    match s.find(separator) {
-        None => None,
-        Some(index) => {
-            match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-                (Ok(l), Ok(r)) => Some((l, r)),
-                _ => None
+// Computes the field for one view with whichever engine suits it. `center`
+// gives the view's center with at least the given number of fraction bits.
+fn compute_field(args: &Args, params: &Params<Fractal>, viewport: &Viewport, limit: usize,
+                 center: impl Fn(u32) -> FixedComplex, reference: Option<Reference>, progress: bool) -> Result<Field, Error> {
+    let bits = precision_for(args.size, viewport);
+    let field = match args.engine_for(params, viewport) {
+        Engine::Double | Engine::Auto => {
+            let tiling = args.tiling();
+            let total = mandelbrot::tile::tiles(args.size, tiling.size)?.len();
+            let done = AtomicUsize::new(0);
+            let Tiled { field, stats, .. } = render_tiled(args.size, viewport, params, limit, &tiling, |_, _| {
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                if progress {
+                    eprint!("\rrendered {}/{} tiles", done, total);
+                }
+                true
+            })?;
+            if progress {
+                eprintln!();
+            }
+            if args.stats {
+                let total = stats.iterations + stats.saved;
+                eprintln!("{} iterations computed, {} saved by interior checks ({:.1}%)",
+                          stats.iterations, stats.saved,
+                          100.0 * stats.saved as f64 / total.max(1) as f64);
+                if stats.filled > 0 {
+                    eprintln!("{} pixels filled without iterating", stats.filled);
+                }
             }
+            field
         }
-    }
+        Engine::Precise => render_precise(args.size, viewport, &center(bits), params, limit)?,
+        Engine::Perturbation => match reference {
+            Some(Reference { orbit, offset }) => {
+                render_perturbed_from(args.size, viewport, orbit, offset, args.radius, limit, !args.no_series)?
+            }
+            None => render_perturbed(args.size, viewport, &center(bits), args.radius, limit, !args.no_series)?,
+        },
+    };
+    Ok(field)
 }
 
-#[test]
-fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("",        ','), None);
-    assert_eq!(parse_pair::<i32>("10,",     ','), None);
-    assert_eq!(parse_pair::<i32>(",10",     ','), None);
-    assert_eq!(parse_pair::<i32>("10,20",   ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x",    'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
-}
+// Renders what `args` describes; `job` is the same, recorded in single
+// images.
+fn render(args: &Args, job: &Table) -> Result<(), Error> {
+    args.validate()?;
+    let colors = args.colors()?;
+
+    if let Some(filename) = &args.load_field {
+        let field = Field::load(filename)?;
+        // Describe the loaded field rather than the size and limit given
+        // alongside it, so the image's job colors it again just as it is.
+        let args = Args { size: field.bounds(), max_iter: Limit::Fixed(field.limit), ..args.clone() };
+        return write_field(&args, &job::for_field(job, &field), &field, &colors);
+    }
 
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair::<f64>(s, ',') {
-        Some((re, im)) => Some(Complex{re: re, im: im}),
-        None => None
+    let params = args.params();
+    if let Some(filename) = &args.scene {
+        return animate::scene(args, filename, &params, &colors);
+    }
+    if args.frames.is_some() {
+        return animate::zoom(args, &params, &colors);
     }
-}
 
-#[test]
-fn test_parse_complex() {
-    assert_eq!(parse_complex("1.25,-0.0625"),
-               Some(Complex { re: 1.25, im: -0.0625 }));
-    assert_eq!(parse_complex(",-0.0625"), None);
+    let viewport = args.viewport()?;
+    let limit = args.max_iter.resolve(&viewport);
+    let center = |bits| args.precise_center(&viewport, bits);
+    let field = compute_field(args, &params, &viewport, limit, center, None, args.progress)?;
+    if let Some(filename) = &args.save_field {
+        field.save(filename)?;
+    }
+    write_field(args, job, &field, &colors)
 }
 
-
-fn pixel_to_point(bounds: (usize, usize), pixel: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>) -> Complex<f64> {
-    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
-    return Complex {
-        re: upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
-        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
-    } 
+// Writes `field`, rendered as `args` and `job` describe, to the output.
+fn write_field(args: &Args, job: &Table, field: &Field, colors: &ColorMap) -> Result<(), Error> {
+    if args.format() == Format::Pfm {
+        return write_float_field(args.output(), field);
+    }
+    let viewport = args.viewport()?;
+    let params = args.params();
+    let text = job::image_text(job, &viewport, field.limit)?;
+    match args.depth {
+        16 => write_pixels::<u16>(args, field, &viewport, &params, colors, &text),
+        _ => write_pixels::<u8>(args, field, &viewport, &params, colors, &text),
+    }
 }
 
-#[test]
-fn test_pixel_to_point() {
-    assert_eq!(pixel_to_point((100, 200), (25, 175),
-                              Complex { re: -1.0, im:  1.0 },
-                              Complex { re:  1.0, im: -1.0 }),
-               Complex { re: -0.5, im: -0.75 });
+// Colors `field` with `C` channels and writes it out.
+fn write_pixels<C: Channel>(args: &Args, field: &Field, viewport: &Viewport, params: &Params<Fractal>,
+                            colors: &ColorMap, text: &[(String, String)]) -> Result<(), Error> {
+    let image: Image<[C; 3]> = match &args.load_field {
+        Some(_) => colorize_as(field, colors),
+        None => supersample_as(field, viewport, params, colors, &args.supersampling()),
+    };
+    write_image_as(args.output(), args.format(), &image, text)
 }
 
-fn escape_time(c: Complex<f64>, limit: usize, radius: f64) -> Option<usize> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        if z.norm_sqr() > radius {
-            return Some(i)
-        }
-        z = z * z + c; 
-        // eprintln!("Value is {}", z.norm_sqr())
+// Renders what `matches` describes, then writes the job that reproduces it
+// if asked to.
+fn run(args: &Args, matches: &ArgMatches) -> Result<(), Error> {
+    let job = job::effective(matches);
+    render(args, &job)?;
+    if args.write_job || args.job.is_some() {
+        job::write(args.output(), &job)?;
     }
-
-    None
+    Ok(())
 }
 
-
-fn render(pixels: &mut [[u8; 3]], bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, radius: f64) {
-    assert!(pixels.len() == bounds.0 * bounds.1);
-    for row in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
-            let energy: u8 = match escape_time(point, 255, radius) {
-                None => 0,
-                Some(count) => 255 - count as u8 
-            };
-            pixels[row * bounds.0 + column] = [ energy, energy * (255 - energy),  energy / (255 - energy)];
+// Renders each of `jobs` in turn, each with the command line's options laid
+// over it, carrying on past jobs that fail. A lone job's error is returned;
+// a batch reports its failures as it goes and returns how many there were.
+fn run_jobs(jobs: Vec<Vec<String>>) -> Result<usize, Error> {
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    let mut failed = 0;
+    for (i, job) in jobs.iter().enumerate() {
+        let argv = command_line[..1].iter().cloned()
+            .chain(job.iter().map(OsString::from))
+            .chain(command_line[1..].iter().cloned());
+        let result = Args::command().try_get_matches_from(argv)
+            .and_then(|matches| Ok((Args::from_arg_matches(&matches)?, matches)))
+            .map_err(clap_error)
+            .and_then(|(args, matches)| run(&args, &matches));
+        if let Err(err) = result {
+            if jobs.len() == 1 {
+                return Err(err);
+            }
+            eprintln!("error: job {} of {}: {}", i + 1, jobs.len(), err);
+            failed += 1;
         }
     }
-} 
-
-fn flatten<T>(data: &[[T; 3]]) -> &[T] {
-    use std::mem::transmute;
-    use std::slice::from_raw_parts;
-    unsafe {
-        transmute( from_raw_parts(data.as_ptr(), data.len() * 3) )
+    if failed > 0 {
+        eprintln!("error: {} of {} jobs failed", failed, jobs.len());
     }
+    Ok(failed)
 }
 
-
-fn write_image(filename: &str, pixels: &[[u8; 3]], bounds: (usize, usize)) -> Result<(), std::io::Error> {
-    let output = File::create(filename)?;
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(flatten::<u8>(pixels), bounds.0 as u32, bounds.1 as u32, ColorType::RGB(8))?;
-    Ok(())
+// Opens the explorer window on what `matches` describes.
+#[cfg(feature = "explore")]
+fn explore(args: &Args, matches: &ArgMatches) -> Result<(), Error> {
+    explore::run(args, matches)
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+#[cfg(not(feature = "explore"))]
+fn explore(_args: &Args, _matches: &ArgMatches) -> Result<(), Error> {
+    Err(Error::Invalid("this build has no explorer window; build it with `--features explore`".to_string()))
+}
 
-    if args.len() != 5 {
-        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT", args[0]);
-        eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20", args[0]);
-        std::process::exit(1);
+// The exit status for `err`: 2 for a bad command line, as clap uses for its
+// own errors, then one status for each other kind.
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::Invalid(_) => 2,
+        Error::Parse(_) => 3,
+        Error::Viewport(_) => 4,
+        Error::Io { .. } => 5,
+        Error::Encoding(_) => 6,
     }
+}
 
-    let bounds = parse_pair::<usize>(&args[2], 'x').expect("error parsing image dimensions");
-    let upper_left = parse_complex(&args[3]).expect("error parsing upper left corner point");
-    let lower_right = parse_complex(&args[4]).expect("error parsing lower right corner point");
-
-    let mut pixels = vec![[0, 0, 0]; bounds.0 * bounds.1];
-     // Scope of slicing up `pixels` into horizontal bands.
-     {
-        let bands: Vec<(usize, &mut [[u8; 3]])> = pixels
-            .chunks_mut(bounds.0)
-            .enumerate()
-            .collect();
-
-        bands.into_par_iter()
-            .for_each(|(i, band)| {
-                let top = i;
-                let band_bounds = (bounds.0, 1);
-                let band_upper_left = pixel_to_point(bounds, (0, top),
-                                                     upper_left, lower_right);
-                let band_lower_right = pixel_to_point(bounds, (bounds.0, top + 1),
-                                                      upper_left, lower_right);
-                render(band, band_bounds, band_upper_left, band_lower_right, 4.0 as f64);
-            });
-     }
-
-    // render(&mut pixels, bounds, upper_left, lower_right);
-    write_image(&args[1], &pixels, bounds).expect("error writing png file");
+fn main() {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let result = match args.threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|err| Error::Invalid(format!("can't start worker threads: {}", err))),
+        None => Ok(()),
+    };
+    let result = result.and_then(|()| match &args.job {
+        Some(filename) => job::load(filename).and_then(run_jobs),
+        None => match &args.rerender {
+            Some(image) => job::from_image(image).and_then(|job| run_jobs(vec![job])),
+            None if args.explore => explore(&args, &matches).map(|()| 0),
+            None if args.preview => preview::run(&matches).map(|()| 0),
+            None => run(&args, &matches).map(|()| 0),
+        },
+    });
+    match result {
+        Ok(0) => {}
+        Ok(_) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(exit_code(&err));
+        }
+    }
 }
@@ -0,0 +1,420 @@
+//! Writing rendered images to disk.
+
+use crate::error::Error;
+use crate::field::Field;
+use crate::pixels::{Channel, Image, Pixel};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::str::FromStr;
+
+/// Image file formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// PNG, 8 or 16 bits per channel.
+    Png,
+    /// Binary PPM, 8 or 16 bits per channel: trivial to read, for piping to
+    /// other tools.
+    Ppm,
+    /// Binary PGM, the grayscale PPM; colors become their luma.
+    Pgm,
+    /// Uncompressed TIFF, 8 or 16 bits per channel.
+    Tiff,
+    /// JPEG at quality 90, for small previews.
+    Jpeg,
+    /// Lossless WebP.
+    WebP,
+    /// Portable float map of smooth iteration counts rather than colors, for
+    /// post-processing; see [`write_float_field`].
+    Pfm,
+}
+
+impl Format {
+    /// The format named by `filename`'s extension, if it names one.
+    pub fn from_filename(filename: &str) -> Option<Format> {
+        let extension = std::path::Path::new(filename).extension()?.to_str()?;
+        extension.to_lowercase().parse().ok()
+    }
+
+    /// Whether the format can hold 16-bit channels.
+    pub fn supports_16_bit(self) -> bool {
+        matches!(self, Format::Png | Format::Ppm | Format::Pgm | Format::Tiff)
+    }
+
+    /// Whether the format can hold an alpha channel.
+    pub fn supports_alpha(self) -> bool {
+        matches!(self, Format::Png | Format::Tiff | Format::WebP)
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format, Error> {
+        match s {
+            "png" => Ok(Format::Png),
+            "ppm" => Ok(Format::Ppm),
+            "pgm" => Ok(Format::Pgm),
+            "tiff" | "tif" => Ok(Format::Tiff),
+            "jpeg" | "jpg" => Ok(Format::Jpeg),
+            "webp" => Ok(Format::WebP),
+            "pfm" => Ok(Format::Pfm),
+            _ => Err(Error::Parse(format!("unknown format '{}', expected png, ppm, pgm, tiff, jpeg, webp or pfm", s))),
+        }
+    }
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(Format::from_filename("out/mandel.TIF"), Some(Format::Tiff));
+    assert_eq!(Format::from_filename("zoom-###.jpg"), Some(Format::Jpeg));
+    assert_eq!(Format::from_filename("mandel"), None);
+    assert_eq!(Format::from_filename("mandel.bmp"), None);
+    assert_eq!("pfm".parse().ok(), Some(Format::Pfm));
+    assert!(!Format::Jpeg.supports_16_bit() && Format::Pgm.supports_16_bit());
+    assert!(!Format::Ppm.supports_alpha() && Format::WebP.supports_alpha());
+}
+
+/// Writes `image` as an 8-bit RGB PNG.
+pub fn write_image(filename: &str, image: &Image<[u8; 3]>) -> Result<(), Error> {
+    write_image_with_text(filename, image, &[])
+}
+
+/// Like [`write_image`], also storing each `(keyword, text)` pair of `text`
+/// in the PNG: as a tEXt chunk when it fits Latin-1, or as an iTXt chunk
+/// when it doesn't.
+pub fn write_image_with_text(filename: &str, image: &Image<[u8; 3]>, text: &[(String, String)]) -> Result<(), Error> {
+    write_image_as(filename, Format::Png, image, text)
+}
+
+// An error writing `filename`.
+fn write_error(filename: &str, err: io::Error) -> Error {
+    Error::io(format!("writing {}", filename), err)
+}
+
+// An error from the PNG encoder writing `filename`.
+fn png_error(filename: &str, err: png::EncodingError) -> Error {
+    match err {
+        png::EncodingError::IoError(err) => write_error(filename, err),
+        err => Error::Encoding(format!("encoding {}: {}", filename, err)),
+    }
+}
+
+// An error from the GIF encoder writing `filename`.
+fn gif_error(filename: &str, err: gif::EncodingError) -> Error {
+    match err {
+        gif::EncodingError::Io(err) => write_error(filename, err),
+        err => Error::Encoding(format!("encoding {}: {}", filename, err)),
+    }
+}
+
+// An error from one of `image`'s encoders writing `filename`.
+fn image_error(filename: &str, err: image::ImageError) -> Error {
+    match err {
+        image::ImageError::IoError(err) => write_error(filename, err),
+        err => Error::Encoding(format!("encoding {}: {}", filename, err)),
+    }
+}
+
+// The color type `image` describes pixels of `P` as.
+fn color_type<P: Pixel>() -> ExtendedColorType {
+    match (P::CHANNELS, P::Channel::BITS) {
+        (4, 16) => ExtendedColorType::Rgba16,
+        (4, _) => ExtendedColorType::Rgba8,
+        (_, 16) => ExtendedColorType::Rgb16,
+        _ => ExtendedColorType::Rgb8,
+    }
+}
+
+/// Writes `image` in `format`, with as many bits per channel and as many
+/// channels as its pixels have. PNGs also store `text` as
+/// [`write_image_with_text`] does; other formats leave it out.
+///
+/// Fails for [`Format::Pfm`], which holds a field rather than colors, and
+/// for 16-bit channels or alpha in a format without them.
+pub fn write_image_as<P: Pixel>(filename: &str, format: Format, image: &Image<P>,
+                                text: &[(String, String)]) -> Result<(), Error> {
+    // Checked before the file is created, so a refused write leaves nothing
+    // behind.
+    const NOT_COLORS: &str = "pfm files hold iteration counts, not colors";
+    let invalid = |message: &str| Error::Invalid(format!("can't write {}: {}", filename, message));
+    if format == Format::Pfm {
+        return Err(invalid(NOT_COLORS));
+    }
+    if P::Channel::BITS == 16 && !format.supports_16_bit() {
+        return Err(invalid("this format can't hold 16-bit channels"));
+    }
+    if P::CHANNELS == 4 && !format.supports_alpha() {
+        return Err(invalid("this format can't hold an alpha channel"));
+    }
+
+    let mut output = BufWriter::new(File::create(filename).map_err(|err| write_error(filename, err))?);
+    let (width, height) = (image.width() as u32, image.height() as u32);
+    match format {
+        Format::Png => write_png(output, image, text).map_err(|err| png_error(filename, err)),
+        Format::Ppm | Format::Pgm => {
+            let max = P::Channel::MAX;
+            let (magic, bytes) = match format {
+                Format::Ppm => ("P6", image.bytes(true)),
+                _ => {
+                    let luma: Vec<P::Channel> = image.channels().chunks_exact(P::CHANNELS)
+                        .map(|rgb| P::Channel::from_f64(0.2126 * rgb[0].to_f64() + 0.7152 * rgb[1].to_f64()
+                                                        + 0.0722 * rgb[2].to_f64()))
+                        .collect();
+                    ("P5", Cow::Owned(P::Channel::bytes(&luma, true).into_owned()))
+                }
+            };
+            write!(output, "{}\n{} {}\n{}\n", magic, width, height, max)
+                .and_then(|()| output.write_all(&bytes))
+                .and_then(|()| output.flush())
+                .map_err(|err| write_error(filename, err))
+        }
+        Format::Tiff => TiffEncoder::new(output)
+            .write_image(&image.bytes(false), width, height, color_type::<P>())
+            .map_err(|err| image_error(filename, err)),
+        Format::Jpeg => JpegEncoder::new_with_quality(output, 90)
+            .write_image(&image.bytes(false), width, height, color_type::<P>())
+            .map_err(|err| image_error(filename, err)),
+        Format::WebP => WebPEncoder::new_lossless(output)
+            .write_image(&image.bytes(false), width, height, color_type::<P>())
+            .map_err(|err| image_error(filename, err)),
+        Format::Pfm => Err(invalid(NOT_COLORS)),
+    }
+}
+
+fn write_png<P: Pixel>(output: BufWriter<File>, image: &Image<P>, text: &[(String, String)]) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(output, image.width() as u32, image.height() as u32);
+    encoder.set_color(if P::CHANNELS == 4 { png::ColorType::Rgba } else { png::ColorType::Rgb });
+    encoder.set_depth(if P::Channel::BITS == 16 { png::BitDepth::Sixteen } else { png::BitDepth::Eight });
+    for (keyword, text) in text {
+        let latin1 = text.chars().all(|c| (c as u32) < 256);
+        if latin1 {
+            encoder.add_text_chunk(keyword.clone(), text.clone())
+        } else {
+            encoder.add_itxt_chunk(keyword.clone(), text.clone())
+        }?;
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.bytes(true))?;
+    writer.finish()
+}
+
+/// Writes the smooth iteration counts of `field` as a portable float map: a
+/// text header of `Pf`, the width and height, and `-1.0` for little-endian
+/// data, then one f32 per pixel with the bottom row first. Points inside
+/// the set are written as -1.
+pub fn write_float_field(filename: &str, field: &Field) -> Result<(), Error> {
+    write_pfm(filename, field).map_err(|err| write_error(filename, err))
+}
+
+fn write_pfm(filename: &str, field: &Field) -> Result<(), io::Error> {
+    let mut output = BufWriter::new(File::create(filename)?);
+    let (width, height) = field.bounds();
+    write!(output, "Pf\n{} {}\n-1.0\n", width, height)?;
+    for row in field.samples.rows().rev() {
+        for sample in row {
+            let value = sample.map_or(-1.0, |escape| escape.smooth as f32);
+            output.write_all(&value.to_le_bytes())?;
+        }
+    }
+    output.flush()
+}
+
+#[test]
+fn test_write_formats() {
+    let bounds = (3, 2);
+    let pixels = Image::from_pixels(bounds, vec![[0, 0, 0], [255, 255, 255], [255, 0, 0],
+                                                 [0, 255, 0], [0, 0, 255], [10u8, 20, 30]]).unwrap();
+    let wide = Image::from_pixels(bounds, pixels.iter().map(|pixel| pixel.map(|c| c as u16 * 257)).collect()).unwrap();
+    let alpha = Image::from_pixels(bounds, pixels.iter().map(|&[r, g, b]| [r, g, b, r / 2]).collect()).unwrap();
+    let dir = std::env::temp_dir();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+    write_image_as(&path("twod-test.ppm"), Format::Ppm, &pixels, &[]).unwrap();
+    let ppm = std::fs::read(path("twod-test.ppm")).unwrap();
+    assert!(ppm.starts_with(b"P6\n3 2\n255\n") && ppm.ends_with(&[0, 0, 255, 10, 20, 30]));
+    write_image_as(&path("twod-test.pgm"), Format::Pgm, &wide, &[]).unwrap();
+    let pgm = std::fs::read(path("twod-test.pgm")).unwrap();
+    assert!(pgm.starts_with(b"P5\n3 2\n65535\n") && pgm.ends_with(&[0x12, 0x7c, 0x12, 0xab]));
+    assert_eq!(pgm.len(), "P5\n3 2\n65535\n".len() + 12);
+
+    write_image_as(&path("twod-test.png"), Format::Png, &wide, &[]).unwrap();
+    let mut reader = png::Decoder::new(File::open(path("twod-test.png")).unwrap()).read_info().unwrap();
+    assert_eq!(reader.info().bit_depth, png::BitDepth::Sixteen);
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    assert_eq!(&data[6..12], &[255; 6]);
+
+    for (name, format) in [("twod-test.tiff", Format::Tiff), ("twod-test.webp", Format::WebP)] {
+        write_image_as(&path(name), format, &pixels, &[]).unwrap();
+        let image = image::open(path(name)).unwrap().into_rgb8();
+        assert_eq!(image.as_raw(), &pixels.concat());
+    }
+    write_image_as(&path("twod-test.tiff"), Format::Tiff, &wide, &[]).unwrap();
+    let image = image::open(path("twod-test.tiff")).unwrap().into_rgb16();
+    assert_eq!(image.as_raw(), &wide.concat());
+    write_image_as(&path("twod-test.jpeg"), Format::Jpeg, &pixels, &[]).unwrap();
+    assert_eq!(image::open(path("twod-test.jpeg")).unwrap().into_rgb8().dimensions(), (3, 2));
+
+    assert!(write_image_as(&path("twod-test.jpeg"), Format::Jpeg, &wide, &[]).is_err());
+    assert!(write_image_as(&path("twod-test.pfm"), Format::Pfm, &pixels, &[]).is_err());
+    assert!(write_image_as(&path("twod-test.ppm"), Format::Ppm, &alpha, &[]).is_err());
+    write_image_as(&path("twod-test.webp"), Format::WebP, &alpha, &[]).unwrap();
+    assert_eq!(image::open(path("twod-test.webp")).unwrap().into_rgba8().as_raw(), &alpha.concat());
+
+    let mut field = Field::new(bounds, 100).unwrap();
+    field.samples[0] = Some(crate::field::Escape { count: 5, smooth: 4.5, z: num::Complex { re: 300.0, im: 0.0 } });
+    write_float_field(&path("twod-test.pfm"), &field).unwrap();
+    let pfm = std::fs::read(path("twod-test.pfm")).unwrap();
+    let header = "Pf\n3 2\n-1.0\n".len();
+    assert_eq!(pfm.len(), header + 24);
+    // The first pixel starts the last row written.
+    assert_eq!(&pfm[header + 12..header + 16], &4.5f32.to_le_bytes());
+    assert_eq!(&pfm[header..header + 4], &(-1.0f32).to_le_bytes());
+
+    for name in ["ppm", "pgm", "png", "tiff", "webp", "jpeg", "pfm"] {
+        std::fs::remove_file(path(&format!("twod-test.{}", name))).unwrap();
+    }
+}
+
+/// The `(keyword, text)` pairs stored in the PNG `filename` ahead of its
+/// image data, from tEXt, zTXt and iTXt chunks alike.
+pub fn read_text(filename: &str) -> Result<Vec<(String, String)>, Error> {
+    let decode_error = |err: png::DecodingError| match err {
+        png::DecodingError::IoError(err) => Error::io(format!("reading {}", filename), err),
+        err => Error::Encoding(format!("decoding {}: {}", filename, err)),
+    };
+    let file = File::open(filename).map_err(|err| Error::io(format!("reading {}", filename), err))?;
+    let reader = png::Decoder::new(BufReader::new(file)).read_info().map_err(decode_error)?;
+    let info = reader.info();
+    let mut text: Vec<(String, String)> = info.uncompressed_latin1_text.iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect();
+    for chunk in &info.compressed_latin1_text {
+        text.push((chunk.keyword.clone(), chunk.get_text().map_err(decode_error)?));
+    }
+    for chunk in &info.utf8_text {
+        text.push((chunk.keyword.clone(), chunk.get_text().map_err(decode_error)?));
+    }
+    Ok(text)
+}
+
+#[test]
+fn test_image_text() {
+    let path = std::env::temp_dir().join("twod-test-text.png");
+    let filename = path.to_str().unwrap();
+    let text = [("Software".to_string(), "mandelbrot".to_string()),
+                ("Palette".to_string(), "gradients/été.txt".to_string()),
+                ("Comment".to_string(), "✓".to_string())];
+    write_image_with_text(filename, &Image::from_pixels((3, 2), vec![[1, 2, 3]; 6]).unwrap(), &text).unwrap();
+    let mut read = read_text(filename).unwrap();
+    read.sort();
+    let mut expected = text.to_vec();
+    expected.sort();
+    assert_eq!(read, expected);
+
+    let mut reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    assert_eq!(&data[..6], &[1, 2, 3, 1, 2, 3]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+enum Encoder {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Apng(png::Writer<BufWriter<File>>),
+}
+
+/// An animated GIF or PNG written a frame at a time.
+pub struct Animation {
+    encoder: Encoder,
+    filename: String,
+    bounds: (usize, usize),
+    delay: u16,
+}
+
+impl Animation {
+    /// Starts an animation of `frames` frames of `bounds` pixels at `fps`
+    /// frames per second, looping forever. Files ending in `.gif` become
+    /// GIFs; anything else is an APNG, which keeps every color exactly.
+    pub fn create(filename: &str, bounds: (usize, usize), frames: usize, fps: u16) -> Result<Animation, Error> {
+        let too_large = || Error::Invalid(format!("can't write {}: animation too large", filename));
+        let output = BufWriter::new(File::create(filename).map_err(|err| write_error(filename, err))?);
+        if filename.to_lowercase().ends_with(".gif") {
+            let (width, height) = (u16::try_from(bounds.0).map_err(|_| too_large())?,
+                                   u16::try_from(bounds.1).map_err(|_| too_large())?);
+            let mut encoder = gif::Encoder::new(output, width, height, &[]).map_err(|err| gif_error(filename, err))?;
+            encoder.set_repeat(gif::Repeat::Infinite).map_err(|err| gif_error(filename, err))?;
+            // GIF delays are in hundredths of a second.
+            let delay = (100.0 / fps as f64).round() as u16;
+            return Ok(Animation { encoder: Encoder::Gif(encoder), filename: filename.to_string(), bounds, delay });
+        }
+
+        let mut encoder = png::Encoder::new(output, bounds.0 as u32, bounds.1 as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let png_error = |err| png_error(filename, err);
+        encoder.set_animated(u32::try_from(frames).map_err(|_| too_large())?, 0).map_err(png_error)?;
+        encoder.set_frame_delay(1, fps).map_err(png_error)?;
+        let writer = encoder.write_header().map_err(png_error)?;
+        Ok(Animation { encoder: Encoder::Apng(writer), filename: filename.to_string(), bounds, delay: 0 })
+    }
+
+    /// Appends a frame, which must have the animation's bounds.
+    pub fn add_frame(&mut self, image: &Image<[u8; 3]>) -> Result<(), Error> {
+        if image.bounds() != self.bounds {
+            return Err(Error::Invalid(format!("frame size differs from the size of {}", self.filename)));
+        }
+        match &mut self.encoder {
+            Encoder::Gif(encoder) => {
+                let mut frame = gif::Frame::from_rgb_speed(self.bounds.0 as u16, self.bounds.1 as u16, image.channels(), 10);
+                frame.delay = self.delay;
+                encoder.write_frame(&frame).map_err(|err| gif_error(&self.filename, err))
+            }
+            Encoder::Apng(writer) => writer.write_image_data(image.channels()).map_err(|err| png_error(&self.filename, err)),
+        }
+    }
+
+    /// Finishes the file. An APNG must have had all its frames added.
+    pub fn finish(self) -> Result<(), Error> {
+        match self.encoder {
+            Encoder::Gif(encoder) => encoder.into_inner()
+                .and_then(|mut output| output.flush())
+                .map_err(|err| write_error(&self.filename, err)),
+            Encoder::Apng(writer) => writer.finish().map_err(|err| png_error(&self.filename, err)),
+        }
+    }
+}
+
+#[test]
+fn test_animation() {
+    let bounds = (8, 6);
+    let frames: Vec<Image<[u8; 3]>> = (0..3)
+        .map(|i| Image::from_pixels(bounds, vec![[i * 80, 40, 200 - i * 50]; 48]).unwrap())
+        .collect();
+    for name in ["twod-test-animation.apng", "twod-test-animation.gif"] {
+        let path = std::env::temp_dir().join(name);
+        let mut animation = Animation::create(path.to_str().unwrap(), bounds, frames.len(), 25).unwrap();
+        for frame in &frames {
+            animation.add_frame(frame).unwrap();
+        }
+        assert!(animation.add_frame(&Image::new((6, 8)).unwrap()).is_err());
+        animation.finish().unwrap();
+        if name.ends_with(".apng") {
+            let reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+            assert_eq!(reader.info().animation_control().map(|control| control.num_frames), Some(3));
+        } else {
+            let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+            let mut count = 0;
+            while decoder.read_next_frame().unwrap().is_some() {
+                count += 1;
+            }
+            assert_eq!(count, 3);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+//! Gradients and the mapping from iteration counts to colors.
+
+use crate::error::Error;
+use crate::pixels::Channel;
+use std::fs;
+use std::str::FromStr;
+
+/// A gradient: colors at positions in [0, 1], linearly interpolated between.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    stops: Vec<(f64, [u8; 3])>,
+}
+
+const GRAYSCALE: &[(f64, [u8; 3])] = &[
+    (0.0, [0, 0, 0]),
+    (1.0, [255, 255, 255]),
+];
+
+const VIRIDIS: &[(f64, [u8; 3])] = &[
+    (0.0, [68, 1, 84]),
+    (0.125, [71, 44, 122]),
+    (0.25, [59, 81, 139]),
+    (0.375, [44, 113, 142]),
+    (0.5, [33, 144, 141]),
+    (0.625, [39, 173, 129]),
+    (0.75, [92, 200, 99]),
+    (0.875, [170, 220, 50]),
+    (1.0, [253, 231, 37]),
+];
+
+const MAGMA: &[(f64, [u8; 3])] = &[
+    (0.0, [0, 0, 4]),
+    (0.125, [28, 16, 68]),
+    (0.25, [79, 18, 123]),
+    (0.375, [129, 37, 129]),
+    (0.5, [181, 54, 122]),
+    (0.625, [229, 80, 100]),
+    (0.75, [251, 135, 97]),
+    (0.875, [254, 194, 135]),
+    (1.0, [252, 253, 191]),
+];
+
+// Ultra Fractal's default blue and gold gradient, closed so it cycles cleanly.
+const CLASSIC: &[(f64, [u8; 3])] = &[
+    (0.0, [0, 7, 100]),
+    (0.16, [32, 107, 203]),
+    (0.42, [237, 255, 255]),
+    (0.6425, [255, 170, 0]),
+    (0.8575, [0, 2, 0]),
+    (1.0, [0, 7, 100]),
+];
+
+/// Names accepted by [`Palette::builtin`].
+pub const BUILTIN_NAMES: &[&str] = &["grayscale", "viridis", "magma", "classic"];
+
+impl Palette {
+    /// A palette from `(position, color)` stops in any order.
+    pub fn new(mut stops: Vec<(f64, [u8; 3])>) -> Result<Palette, Error> {
+        if stops.is_empty() {
+            return Err(Error::Parse("palette has no color stops".to_string()));
+        }
+        if let Some((position, _)) = stops.iter().find(|(p, _)| !(0.0..=1.0).contains(p)) {
+            return Err(Error::Parse(format!("color stop position {} is outside 0..1", position)));
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Palette { stops })
+    }
+
+    /// One of the palettes named in [`BUILTIN_NAMES`].
+    pub fn builtin(name: &str) -> Option<Palette> {
+        let stops = match name {
+            "grayscale" | "greyscale" => GRAYSCALE,
+            "viridis" => VIRIDIS,
+            "magma" => MAGMA,
+            "classic" => CLASSIC,
+            _ => return None,
+        };
+        Some(Palette { stops: stops.to_vec() })
+    }
+
+    /// Parses a gradient file: one `POSITION COLOR` stop per line, where COLOR
+    /// is `#rrggbb` or `r,g,b`. Blank lines and lines starting with `#` are
+    /// ignored.
+    pub fn parse(text: &str) -> Result<Palette, Error> {
+        let mut stops = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let stop = line.split_once(char::is_whitespace)
+                .and_then(|(position, color)| {
+                    Some((f64::from_str(position).ok()?, parse_color(color.trim())?))
+                });
+            match stop {
+                Some(stop) => stops.push(stop),
+                None => return Err(Error::Parse(format!("line {}: expected POSITION COLOR, got '{}'", number + 1, line))),
+            }
+        }
+        Palette::new(stops)
+    }
+
+    /// Reads and parses a gradient file.
+    pub fn load(path: &str) -> Result<Palette, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| Error::io(format!("reading palette {}", path), err))?;
+        Palette::parse(&text).map_err(|err| Error::Parse(format!("{}: {}", path, err)))
+    }
+
+    /// The color at `t`, clamped to the ends of the gradient.
+    pub fn sample(&self, t: f64) -> [u8; 3] {
+        self.sample_exact(t).map(u8::from_f64)
+    }
+
+    // The color at `t` before rounding, on the scale of 0 to 255.
+    fn sample_exact(&self, t: f64) -> [f64; 3] {
+        let after = self.stops.iter().position(|&(p, _)| p >= t);
+        let (p1, c1) = match after {
+            None => return self.stops[self.stops.len() - 1].1.map(f64::from),
+            Some(0) => return self.stops[0].1.map(f64::from),
+            Some(i) => self.stops[i],
+        };
+        let (p0, c0) = self.stops[after.unwrap() - 1];
+        let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+        let mut color = [0.0; 3];
+        for i in 0..3 {
+            color[i] = c0[i] as f64 + (c1[i] as f64 - c0[i] as f64) * f;
+        }
+        color
+    }
+}
+
+#[test]
+fn test_palette_sample() {
+    let palette = Palette::new(vec![(0.5, [200, 100, 0]), (0.0, [0, 0, 0])]).unwrap();
+    assert_eq!(palette.sample(-1.0), [0, 0, 0]);
+    assert_eq!(palette.sample(0.0), [0, 0, 0]);
+    assert_eq!(palette.sample(0.25), [100, 50, 0]);
+    assert_eq!(palette.sample(0.5), [200, 100, 0]);
+    assert_eq!(palette.sample(0.9), [200, 100, 0]);
+
+    for name in BUILTIN_NAMES {
+        assert!(Palette::builtin(name).is_some());
+    }
+    assert_eq!(Palette::builtin("classic").unwrap().sample(0.0), [0, 7, 100]);
+}
+
+#[test]
+fn test_palette_parse() {
+    let palette = Palette::parse("# sunset\n0 #000000\n\n1.0 255,128,0\n").unwrap();
+    assert_eq!(palette.sample(0.5), [128, 64, 0]);
+    assert!(Palette::parse("").is_err());
+    assert!(Palette::parse("0.5").is_err());
+    assert!(Palette::parse("1.5 #ffffff").is_err());
+    assert!(Palette::parse("0 #fffff").is_err());
+}
+
+/// Parses a color written as `#rrggbb` or `r,g,b`.
+pub fn parse_color(s: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some([channel(0)?, channel(2)?, channel(4)?]);
+    }
+    let mut channels = s.split(',').map(|c| u8::from_str(c.trim()).ok());
+    let color = [channels.next()??, channels.next()??, channels.next()??];
+    match channels.next() {
+        None => Some(color),
+        Some(_) => None,
+    }
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color("#ff8000"), Some([255, 128, 0]));
+    assert_eq!(parse_color("12, 34,56"), Some([12, 34, 56]));
+    assert_eq!(parse_color("#ff80"), None);
+    assert_eq!(parse_color("1,2"), None);
+    assert_eq!(parse_color("1,2,3,4"), None);
+    assert_eq!(parse_color("1,2,300"), None);
+}
+
+/// What happens to palette positions outside 0..1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    /// Repeat the palette end to end.
+    Cyclic,
+    /// Hold the end colors.
+    Clamp,
+}
+
+impl FromStr for Wrap {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Wrap, Error> {
+        match s {
+            "cyclic" => Ok(Wrap::Cyclic),
+            "clamp" => Ok(Wrap::Clamp),
+            _ => Err(Error::Parse(format!("unknown wrap mode '{}', expected cyclic or clamp", s))),
+        }
+    }
+}
+
+/// How continuous iteration counts become pixel colors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorMap {
+    /// The gradient to sample.
+    pub palette: Palette,
+    /// How positions past the ends of the palette are handled.
+    pub wrap: Wrap,
+    /// Shifts the palette along the iteration counts, as a fraction of the
+    /// palette.
+    pub offset: f64,
+    /// How many iterations one pass through the palette spans. Colors
+    /// depend on the count alone, so raising the iteration limit only adds
+    /// detail.
+    pub period: f64,
+    /// Color for points that never escape.
+    pub interior: [u8; 3],
+}
+
+impl ColorMap {
+    /// Lays `palette` over every 256 iterations, cyclically, with black for
+    /// the interior.
+    pub fn new(palette: Palette) -> ColorMap {
+        ColorMap {
+            palette,
+            wrap: Wrap::Cyclic,
+            offset: 0.0,
+            period: 256.0,
+            interior: [0, 0, 0],
+        }
+    }
+
+    /// The color for a point with continuous iteration count `smooth`, or
+    /// `None` if it never escaped.
+    pub fn color(&self, smooth: Option<f64>) -> [u8; 3] {
+        self.color_as(smooth)
+    }
+
+    /// Like [`ColorMap::color`], with channels of type `C`. Wider channels
+    /// keep the fractions of the gradient that `u8` rounds away.
+    pub fn color_as<C: Channel>(&self, smooth: Option<f64>) -> [C; 3] {
+        let color = match smooth {
+            None => self.interior.map(f64::from),
+            Some(smooth) => {
+                let t = smooth / self.period + self.offset;
+                match self.wrap {
+                    Wrap::Cyclic => self.palette.sample_exact(t.rem_euclid(1.0)),
+                    Wrap::Clamp => self.palette.sample_exact(t),
+                }
+            }
+        };
+        color.map(|value| C::from_f64(value * (C::MAX / 255.0)))
+    }
+}
+
+#[test]
+fn test_color_map() {
+    let palette = Palette::new(vec![(0.0, [0, 0, 0]), (1.0, [100, 100, 100])]).unwrap();
+    let mut map = ColorMap::new(palette);
+    map.period = 100.0;
+    map.interior = [1, 2, 3];
+    assert_eq!(map.color(None), [1, 2, 3]);
+    assert_eq!(map.color(Some(50.0)), [50, 50, 50]);
+
+    map.period = 50.0;
+    assert_eq!(map.color(Some(75.0)), [50, 50, 50]);
+    map.wrap = Wrap::Clamp;
+    assert_eq!(map.color(Some(75.0)), [100, 100, 100]);
+
+    map.period = 100.0;
+    map.offset = 0.25;
+    assert_eq!(map.color(Some(50.0)), [75, 75, 75]);
+    map.wrap = Wrap::Cyclic;
+    assert_eq!(map.color(Some(100.0)), [25, 25, 25]);
+    assert_eq!(map.color(Some(250.0)), [75, 75, 75]);
+
+    // 16-bit channels keep what rounding to 8 bits loses.
+    assert_eq!(map.color_as::<u16>(Some(50.2)), [0x4b7e; 3]);
+    assert_eq!(map.color(Some(50.2)), [75, 75, 75]);
+    assert_eq!(map.color_as::<u16>(None), [0x0101, 0x0202, 0x0303]);
+}
@@ -0,0 +1,247 @@
+//! Perturbation rendering for deep zooms of the Mandelbrot set.
+//!
+//! Only one orbit, at the center of the view, is iterated in arbitrary
+//! precision. Every pixel then iterates just its small difference from that
+//! reference in `f64`:
+//!
+//! ```text
+//! dz' = (2 Z + dz) dz + dc
+//! ```
+//!
+//! where `Z` is the reference orbit and `dc` the pixel's offset from the
+//! center. When a pixel's orbit comes closer to zero than its difference from
+//! the reference (where the difference would lose precision, showing up as
+//! glitches), or the reference runs out, the pixel is rebased onto the start
+//! of the reference orbit and carries on. A series approximation can skip the
+//! early iterations, where every pixel still follows the reference closely.
+//!
+//! Deltas are plain `f64`s, so views must be wider than about 1e-290.
+
+use crate::error::Error;
+use crate::field::{Escape, Field};
+use crate::fixed::FixedComplex;
+use crate::render::smooth_count;
+use crate::viewport::{position_to_offset, Viewport};
+use num::Complex;
+use rayon::prelude::*;
+
+/// The narrowest pixel spacing `f64` deltas can represent with room to spare.
+pub const MIN_PIXEL_SPACING: f64 = 1e-290;
+
+/// Iterates `z^2 + c` from zero in full precision, returning every `z` as
+/// `f64` up to and including the first to pass `radius`, or `limit + 1`
+/// values if none does.
+pub fn reference_orbit(c: &FixedComplex, limit: usize, radius: f64) -> Vec<Complex<f64>> {
+    let mut z = FixedComplex::zero(c.bits());
+    let mut orbit = Vec::new();
+    for _ in 0..limit {
+        let point = z.to_complex();
+        orbit.push(point);
+        if point.norm_sqr() > radius * radius {
+            return orbit;
+        }
+        z = z.square().add(c);
+    }
+    orbit.push(z.to_complex());
+    orbit
+}
+
+#[test]
+fn test_reference_orbit() {
+    let c = FixedComplex::from_complex(Complex { re: 1.0, im: 0.0 }, 64);
+    let orbit = reference_orbit(&c, 100, 2.0);
+    let expected: Vec<Complex<f64>> = [0.0, 1.0, 2.0, 5.0].iter()
+        .map(|&re| Complex { re, im: 0.0 })
+        .collect();
+    assert_eq!(orbit, expected);
+    assert_eq!(reference_orbit(&FixedComplex::zero(64), 10, 2.0).len(), 11);
+}
+
+/// Coefficients of `dz_n ~ A dc + B dc^2 + C dc^3`, valid for the first
+/// `skip` iterations of every pixel in the view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Series {
+    /// Iterations the approximation replaces.
+    pub skip: usize,
+    /// `A`, `B` and `C`.
+    pub coefficients: [Complex<f64>; 3],
+}
+
+impl Series {
+    /// No skipping at all.
+    pub fn none() -> Series {
+        let zero = Complex { re: 0.0, im: 0.0 };
+        Series { skip: 0, coefficients: [zero; 3] }
+    }
+
+    /// `dz` after `skip` iterations for a pixel at offset `dc`.
+    pub fn evaluate(&self, dc: Complex<f64>) -> Complex<f64> {
+        let [a, b, c] = self.coefficients;
+        ((c * dc + b) * dc + a) * dc
+    }
+}
+
+// How closely the series must track directly iterated probe points.
+const SERIES_TOLERANCE: f64 = 1e-6;
+
+/// Finds how many iterations the series can skip for pixels at offsets like
+/// `probes`, which should include the corners of the view. The series is
+/// trusted while its cubic term stays small and it agrees with the probes
+/// iterated directly.
+pub fn series_approximation(orbit: &[Complex<f64>], probes: &[Complex<f64>]) -> Series {
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let delta = probes.iter().map(|p| p.norm()).fold(0.0, f64::max);
+    let mut series = Series::none();
+    let mut coefficients = [zero; 3];
+    let mut deltas = vec![zero; probes.len()];
+
+    for (n, &z) in orbit.iter().enumerate().take(orbit.len().saturating_sub(1)) {
+        let [a, b, c] = coefficients;
+        let next = [
+            z * a * 2.0 + 1.0,
+            z * b * 2.0 + a * a,
+            z * c * 2.0 + a * b * 2.0,
+        ];
+        for (dz, &dc) in deltas.iter_mut().zip(probes) {
+            *dz = (z * 2.0 + *dz) * *dz + dc;
+        }
+
+        let finite = next.iter().all(|k| k.re.is_finite() && k.im.is_finite());
+        let small_cubic = next[2].norm() * delta <= 1e-3 * next[1].norm();
+        let candidate = Series { skip: n + 1, coefficients: next };
+        let tracks = probes.iter().zip(&deltas).all(|(&dc, &dz)| {
+            let reference = orbit[n + 1];
+            // A probe about to need rebasing is past where the series holds.
+            (reference + dz).norm_sqr() >= dz.norm_sqr()
+                && (candidate.evaluate(dc) - dz).norm() <= SERIES_TOLERANCE * dz.norm()
+        });
+        if !(finite && small_cubic && tracks) {
+            break;
+        }
+        coefficients = next;
+        series = candidate;
+    }
+    series
+}
+
+/// Iterates the pixel at offset `dc` from the reference, starting after the
+/// iterations `series` skips.
+pub fn perturbed_escape_time(orbit: &[Complex<f64>], series: &Series, dc: Complex<f64>, limit: usize, radius: f64) -> Option<Escape> {
+    let last = orbit.len() - 1;
+    let mut dz = series.evaluate(dc);
+    let mut m = series.skip;
+    for n in series.skip..limit {
+        let z = orbit[m] + dz;
+        if z.norm_sqr() > radius * radius {
+            return Some(Escape { count: n, smooth: smooth_count(n, z, radius, 2.0), z });
+        }
+        if z.norm_sqr() < dz.norm_sqr() || m == last {
+            // Rebase: the reference starts at zero, so the whole of z
+            // becomes the difference from its start.
+            dz = z;
+            m = 0;
+        }
+        dz = (orbit[m] * 2.0 + dz) * dz + dc;
+        m += 1;
+    }
+    None
+}
+
+/// Renders the Mandelbrot set around `center`, given in full precision, by
+/// perturbation. `viewport` supplies the view's size and rotation; its
+/// `center` is unused. With `use_series` off, every iteration is computed.
+/// Fails if the image has more pixels than a `usize` can count.
+pub fn render_perturbed(bounds: (usize, usize), viewport: &Viewport, center: &FixedComplex, radius: f64, limit: usize,
+                        use_series: bool) -> Result<Field, Error> {
+    let orbit = reference_orbit(center, limit, radius);
+    render_perturbed_from(bounds, viewport, &orbit, Complex { re: 0.0, im: 0.0 }, radius, limit, use_series)
+}
+
+/// Like [`render_perturbed`], around a reference `orbit` already computed for
+/// a point `offset` away from the view's center (the center minus the
+/// reference point). Views near one point, like the frames of a zoom, can
+/// share a single orbit this way; it should have been computed with the
+/// largest `limit` any of them uses.
+pub fn render_perturbed_from(bounds: (usize, usize), viewport: &Viewport, orbit: &[Complex<f64>], offset: Complex<f64>,
+                             radius: f64, limit: usize, use_series: bool) -> Result<Field, Error> {
+    let series = if use_series {
+        let (w, h) = (bounds.0 as f64, bounds.1 as f64);
+        let probes: Vec<Complex<f64>> = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h), (w / 2.0, 0.0), (0.0, h / 2.0)]
+            .iter()
+            .map(|&position| offset + position_to_offset(bounds, position, viewport))
+            .collect();
+        series_approximation(orbit, &probes)
+    } else {
+        Series::none()
+    };
+
+    let mut field = Field::new(bounds, limit)?;
+    field.samples.par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            for (column, sample) in band.iter_mut().enumerate() {
+                let dc = offset + position_to_offset(bounds, (column as f64, row as f64), viewport);
+                *sample = perturbed_escape_time(orbit, &series, dc, limit, radius);
+            }
+        });
+    Ok(field)
+}
+
+#[test]
+fn test_perturbation_matches_direct_iteration() {
+    use crate::formula::Mandelbrot;
+    use crate::render::{render, Mode, Params};
+
+    // Shallow enough for plain f64 to be right, with the reference orbit
+    // escaping early so most pixels must rebase.
+    let bounds = (60, 40);
+    let viewport = Viewport::from_zoom(Complex { re: -0.74, im: 0.16 }, 30.0, 0.0).fit(bounds);
+    let params = Params { formula: Mandelbrot, mode: Mode::Mandelbrot, radius: 256.0, interior_checks: true };
+    let direct = render(bounds, &viewport, &params, 500).unwrap();
+    let center = FixedComplex::from_complex(viewport.center, 80);
+    for use_series in [false, true] {
+        let perturbed = render_perturbed(bounds, &viewport, &center, 256.0, 500, use_series).unwrap();
+        let agree = direct.samples.iter().zip(&perturbed.samples)
+            .filter(|(a, b)| a.map(|e| e.count) == b.map(|e| e.count))
+            .count();
+        assert!(agree >= direct.samples.len() * 99 / 100, "{} of {} agree", agree, direct.samples.len());
+    }
+}
+
+#[test]
+fn test_render_perturbed_from_shared_orbit() {
+    // One orbit at a point off to the side serves a view elsewhere.
+    let bounds = (40, 30);
+    let viewport = Viewport::from_zoom(Complex { re: -0.745, im: 0.112 }, 200.0, 0.0).fit(bounds);
+    let reference = Complex { re: -0.7452, im: 0.1121 };
+    let limit = 800;
+    let orbit = reference_orbit(&FixedComplex::from_complex(reference, 80), 2 * limit, 256.0);
+    let shared = render_perturbed_from(bounds, &viewport, &orbit, viewport.center - reference, 256.0, limit, true).unwrap();
+    let own = render_perturbed(bounds, &viewport, &FixedComplex::from_complex(viewport.center, 80), 256.0, limit, true).unwrap();
+    let agree = own.samples.iter().zip(&shared.samples)
+        .filter(|(a, b)| a.map(|e| e.count) == b.map(|e| e.count))
+        .count();
+    assert!(agree >= own.samples.len() * 99 / 100, "{} of {} agree", agree, own.samples.len());
+}
+
+#[test]
+fn test_series_skips_iterations_deep() {
+    use crate::precise::precision_for;
+
+    let bounds = (40, 40);
+    let text = "-0.743643887037158704752191506114774,0.131825904205311970493132056385139";
+    let viewport = Viewport::from_zoom(Complex { re: -0.74, im: 0.13 }, 1e25, 0.0).fit(bounds);
+    let center = FixedComplex::parse(text, precision_for(bounds, &viewport)).unwrap();
+    let orbit = reference_orbit(&center, 3000, 256.0);
+    let corner = position_to_offset(bounds, (0.0, 0.0), &viewport);
+    let series = series_approximation(&orbit, &[corner, -corner]);
+    assert!(series.skip > 100, "skip {}", series.skip);
+
+    // The series lands where direct perturbation does.
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let mut dz = zero;
+    for &z in &orbit[..series.skip] {
+        dz = (z * 2.0 + dz) * dz + corner;
+    }
+    assert!((series.evaluate(corner) - dz).norm() <= 1e-5 * dz.norm());
+}
@@ -0,0 +1,355 @@
+//! Images as typed pixel buffers that know their own size.
+
+use crate::error::Error;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+
+/// The type of one color channel: `u8`, or `u16` for gradients too smooth
+/// for 256 levels.
+pub trait Channel: Copy + Default + PartialEq + Debug + Send + Sync {
+    /// The brightest value.
+    const MAX: f64;
+    /// Bits per channel.
+    const BITS: u32;
+
+    /// `value`, on the scale of 0 to `MAX`, rounded and clamped.
+    fn from_f64(value: f64) -> Self;
+
+    /// The channel's value on the scale of 0 to `MAX`.
+    fn to_f64(self) -> f64;
+
+    /// `channels` as bytes, most significant first if `big_endian`,
+    /// otherwise in the machine's order. Borrowed where no conversion is
+    /// needed.
+    fn bytes(channels: &[Self], big_endian: bool) -> Cow<'_, [u8]>;
+}
+
+impl Channel for u8 {
+    const MAX: f64 = 255.0;
+    const BITS: u32 = 8;
+
+    fn from_f64(value: f64) -> u8 {
+        value.round() as u8
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn bytes(channels: &[u8], _big_endian: bool) -> Cow<'_, [u8]> {
+        Cow::Borrowed(channels)
+    }
+}
+
+impl Channel for u16 {
+    const MAX: f64 = 65535.0;
+    const BITS: u32 = 16;
+
+    fn from_f64(value: f64) -> u16 {
+        value.round() as u16
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn bytes(channels: &[u16], big_endian: bool) -> Cow<'_, [u8]> {
+        let bytes = channels.iter()
+            .flat_map(|&channel| if big_endian { channel.to_be_bytes() } else { channel.to_ne_bytes() })
+            .collect();
+        Cow::Owned(bytes)
+    }
+}
+
+/// A pixel format: RGB or RGBA, with channels of some [`Channel`] type.
+pub trait Pixel: Copy + Default + PartialEq + Debug + Send + Sync {
+    /// The type of each channel.
+    type Channel: Channel;
+    /// Channels per pixel.
+    const CHANNELS: usize;
+
+    /// The channels of `pixels`, one pixel after another.
+    fn channels(pixels: &[Self]) -> &[Self::Channel];
+}
+
+impl<C: Channel> Pixel for [C; 3] {
+    type Channel = C;
+    const CHANNELS: usize = 3;
+
+    fn channels(pixels: &[[C; 3]]) -> &[C] {
+        pixels.as_flattened()
+    }
+}
+
+impl<C: Channel> Pixel for [C; 4] {
+    type Channel = C;
+    const CHANNELS: usize = 4;
+
+    fn channels(pixels: &[[C; 4]]) -> &[C] {
+        pixels.as_flattened()
+    }
+}
+
+/// A rectangle of pixels stored in row-major order.
+///
+/// Dereferences to the slice of all its pixels, so it can be indexed and
+/// iterated like one. Besides colors it holds anything laid out per pixel,
+/// such as the samples of a field while its tiles come in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image<P> {
+    bounds: (usize, usize),
+    pixels: Vec<P>,
+}
+
+// The number of pixels in an image of `bounds`, if it fits in a `usize`.
+fn area(bounds: (usize, usize)) -> Result<usize, Error> {
+    bounds.0.checked_mul(bounds.1)
+        .ok_or_else(|| Error::Invalid(format!("an image of {}x{} has too many pixels", bounds.0, bounds.1)))
+}
+
+impl<P: Clone + Default> Image<P> {
+    /// An image of `bounds` pixels, all the default value. Fails if there
+    /// are more than a `usize` can count.
+    pub fn new(bounds: (usize, usize)) -> Result<Image<P>, Error> {
+        Ok(Image { bounds, pixels: vec![P::default(); area(bounds)?] })
+    }
+
+    /// An image the size of `other`, all the default value.
+    pub fn like<Q>(other: &Image<Q>) -> Image<P> {
+        Image { bounds: other.bounds, pixels: vec![P::default(); other.pixels.len()] }
+    }
+}
+
+impl<P> Image<P> {
+    /// The image of `bounds` pixels made of `pixels`, which must hold
+    /// exactly that many.
+    pub fn from_pixels(bounds: (usize, usize), pixels: Vec<P>) -> Result<Image<P>, Error> {
+        if pixels.len() != area(bounds)? {
+            return Err(Error::Invalid(format!("{} pixels can't make an image of {}x{}", pixels.len(), bounds.0, bounds.1)));
+        }
+        Ok(Image { bounds, pixels })
+    }
+
+    /// Width and height in pixels.
+    pub fn bounds(&self) -> (usize, usize) {
+        self.bounds
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> usize {
+        self.bounds.0
+    }
+
+    /// Height in pixels.
+    pub fn height(&self) -> usize {
+        self.bounds.1
+    }
+
+    /// The pixels, giving up the image.
+    pub fn into_pixels(self) -> Vec<P> {
+        self.pixels
+    }
+
+    /// Row `y`, or `None` below the bottom row.
+    pub fn row(&self, y: usize) -> Option<&[P]> {
+        if y >= self.bounds.1 {
+            return None;
+        }
+        Some(&self.pixels[y * self.bounds.0..(y + 1) * self.bounds.0])
+    }
+
+    /// Row `y`, for writing, or `None` below the bottom row.
+    pub fn row_mut(&mut self, y: usize) -> Option<&mut [P]> {
+        if y >= self.bounds.1 {
+            return None;
+        }
+        Some(&mut self.pixels[y * self.bounds.0..(y + 1) * self.bounds.0])
+    }
+
+    /// The rows, top to bottom.
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, P> {
+        self.pixels.chunks_exact(self.bounds.0.max(1))
+    }
+
+    /// The rows, top to bottom, for writing.
+    pub fn rows_mut(&mut self) -> std::slice::ChunksExactMut<'_, P> {
+        self.pixels.chunks_exact_mut(self.bounds.0.max(1))
+    }
+
+    /// The rows, for writing in parallel.
+    pub fn par_rows_mut(&mut self) -> rayon::slice::ChunksExactMut<'_, P> where P: Send {
+        self.pixels.par_chunks_exact_mut(self.bounds.0.max(1))
+    }
+
+    // The range of `pixels` spanning a rectangle, checked against the image.
+    fn span(&self, left: usize, top: usize, bounds: (usize, usize)) -> Result<std::ops::Range<usize>, Error> {
+        let fits = |start: usize, len: usize, limit: usize| start.checked_add(len).is_some_and(|end| end <= limit);
+        if !fits(left, bounds.0, self.bounds.0) || !fits(top, bounds.1, self.bounds.1) {
+            return Err(Error::Invalid(format!("a view of {}x{} at ({}, {}) reaches outside a {}x{} image",
+                                              bounds.0, bounds.1, left, top, self.bounds.0, self.bounds.1)));
+        }
+        let start = top * self.bounds.0 + left;
+        match bounds.1 {
+            0 => Ok(start..start),
+            height => Ok(start..start + (height - 1) * self.bounds.0 + bounds.0),
+        }
+    }
+
+    /// The rectangle of `bounds` pixels whose upper left corner is at
+    /// (`left`, `top`). Fails if it reaches outside the image.
+    pub fn view(&self, left: usize, top: usize, bounds: (usize, usize)) -> Result<View<'_, P>, Error> {
+        let span = self.span(left, top, bounds)?;
+        Ok(View { pixels: &self.pixels[span], stride: self.bounds.0, bounds })
+    }
+
+    /// Like [`Image::view`], for writing.
+    pub fn view_mut(&mut self, left: usize, top: usize, bounds: (usize, usize)) -> Result<ViewMut<'_, P>, Error> {
+        let span = self.span(left, top, bounds)?;
+        Ok(ViewMut { pixels: &mut self.pixels[span], stride: self.bounds.0, bounds })
+    }
+}
+
+impl<P: Pixel> Image<P> {
+    /// The channels of every pixel in order.
+    pub fn channels(&self) -> &[P::Channel] {
+        P::channels(&self.pixels)
+    }
+
+    /// The channels as bytes; see [`Channel::bytes`].
+    pub fn bytes(&self, big_endian: bool) -> Cow<'_, [u8]> {
+        P::Channel::bytes(self.channels(), big_endian)
+    }
+}
+
+impl<P> Deref for Image<P> {
+    type Target = [P];
+
+    fn deref(&self) -> &[P] {
+        &self.pixels
+    }
+}
+
+impl<P> DerefMut for Image<P> {
+    fn deref_mut(&mut self) -> &mut [P] {
+        &mut self.pixels
+    }
+}
+
+impl<'a, P> IntoIterator for &'a Image<P> {
+    type Item = &'a P;
+    type IntoIter = std::slice::Iter<'a, P>;
+
+    fn into_iter(self) -> std::slice::Iter<'a, P> {
+        self.pixels.iter()
+    }
+}
+
+/// A rectangle borrowed from an [`Image`].
+#[derive(Clone, Copy, Debug)]
+pub struct View<'a, P> {
+    pixels: &'a [P],
+    stride: usize,
+    bounds: (usize, usize),
+}
+
+impl<'a, P> View<'a, P> {
+    /// Width and height in pixels.
+    pub fn bounds(&self) -> (usize, usize) {
+        self.bounds
+    }
+
+    /// Row `y` of the rectangle, or `None` below its bottom row.
+    pub fn row(&self, y: usize) -> Option<&'a [P]> {
+        if y >= self.bounds.1 {
+            return None;
+        }
+        Some(&self.pixels[y * self.stride..y * self.stride + self.bounds.0])
+    }
+
+    /// The rectangle's rows, top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &'a [P]> + '_ {
+        (0..self.bounds.1).filter_map(|y| self.row(y))
+    }
+}
+
+impl<P: Clone> View<'_, P> {
+    /// A copy of the rectangle as an image of its own.
+    pub fn to_image(&self) -> Image<P> {
+        Image { bounds: self.bounds, pixels: self.rows().flatten().cloned().collect() }
+    }
+}
+
+/// A rectangle of an [`Image`] borrowed for writing.
+#[derive(Debug)]
+pub struct ViewMut<'a, P> {
+    pixels: &'a mut [P],
+    stride: usize,
+    bounds: (usize, usize),
+}
+
+impl<P> ViewMut<'_, P> {
+    /// Width and height in pixels.
+    pub fn bounds(&self) -> (usize, usize) {
+        self.bounds
+    }
+
+    /// Row `y` of the rectangle, or `None` below its bottom row.
+    pub fn row_mut(&mut self, y: usize) -> Option<&mut [P]> {
+        if y >= self.bounds.1 {
+            return None;
+        }
+        Some(&mut self.pixels[y * self.stride..y * self.stride + self.bounds.0])
+    }
+}
+
+impl<P: Clone> ViewMut<'_, P> {
+    /// Overwrites the rectangle with `pixels`, given in row-major order.
+    /// Fails unless there is exactly one for each of its pixels.
+    pub fn copy_from(&mut self, pixels: &[P]) -> Result<(), Error> {
+        if pixels.len() != self.bounds.0 * self.bounds.1 {
+            return Err(Error::Invalid(format!("{} pixels can't fill a {}x{} view",
+                                              pixels.len(), self.bounds.0, self.bounds.1)));
+        }
+        for (y, row) in pixels.chunks_exact(self.bounds.0.max(1)).enumerate() {
+            if let Some(target) = self.row_mut(y) {
+                target.clone_from_slice(row);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_image() {
+    let mut image: Image<[u8; 3]> = Image::new((4, 3)).unwrap();
+    assert_eq!((image.width(), image.height(), image.len()), (4, 3, 12));
+    image.row_mut(1).unwrap()[2] = [1, 2, 3];
+    assert!(image.row(3).is_none() && image.row_mut(3).is_none());
+    image.view_mut(1, 2, (3, 1)).unwrap().copy_from(&[[9, 9, 9]; 3]).unwrap();
+    assert_eq!(image[6], [1, 2, 3]);
+    assert_eq!(image.rows().nth(2).unwrap(), &[[0, 0, 0], [9, 9, 9], [9, 9, 9], [9, 9, 9]]);
+
+    let view = image.view(2, 1, (2, 2)).unwrap();
+    assert_eq!(view.row(0), Some(&[[1, 2, 3], [0, 0, 0]][..]));
+    assert!(view.row(2).is_none());
+    assert_eq!(view.to_image().into_pixels(), [[1, 2, 3], [0, 0, 0], [9, 9, 9], [9, 9, 9]]);
+    assert_eq!(&image.bytes(true)[18..21], &[1, 2, 3]);
+    assert_eq!(image.channels().len(), 36);
+
+    let wide = Image::from_pixels((1, 1), vec![[0x0102u16, 0, 0xffff, 7]]).unwrap();
+    assert_eq!(&*wide.bytes(true), &[1, 2, 0, 0, 0xff, 0xff, 0, 7]);
+    assert!(Image::from_pixels((2, 2), vec![[0u8; 3]; 3]).is_err());
+    assert!(matches!(Image::<u8>::new((1 << 32, (1 << 32) + 1)), Err(Error::Invalid(_))));
+    assert!(matches!(Image::from_pixels((usize::MAX, 2), vec![(); usize::MAX - 1]), Err(Error::Invalid(_))));
+}
+
+#[test]
+fn test_view_outside_image() {
+    let mut image: Image<u8> = Image::new((4, 3)).unwrap();
+    assert!(matches!(image.view(2, 0, (3, 1)), Err(Error::Invalid(_))));
+    assert!(matches!(image.view(usize::MAX, 0, (2, 1)), Err(Error::Invalid(_))));
+    assert!(matches!(image.view_mut(0, 0, (2, 2)).unwrap().copy_from(&[1, 2, 3]), Err(Error::Invalid(_))));
+    assert!(image.view_mut(0, 0, (2, 2)).unwrap().row_mut(2).is_none());
+}
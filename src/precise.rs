@@ -0,0 +1,128 @@
+//! Rendering in arbitrary precision, for views too deep for `f64`.
+
+use crate::error::Error;
+use crate::field::{Escape, Field};
+use crate::fixed::FixedComplex;
+use crate::formula::Formula;
+use crate::render::{smooth_count, Mode, Params};
+use crate::viewport::{position_to_offset, Viewport};
+use rayon::prelude::*;
+
+// Extra fraction bits beyond the pixel spacing, so rounding error stays
+// well below a pixel over long orbits.
+const GUARD_BITS: u32 = 32;
+
+/// Whether neighbouring pixels of `viewport` are too close together for
+/// `f64` to tell apart reliably.
+pub fn needs_precision(bounds: (usize, usize), viewport: &Viewport) -> bool {
+    let magnitude = viewport.center.re.abs().max(viewport.center.im.abs()).max(2.0);
+    viewport.pixel_size(bounds) < magnitude * 1e-13
+}
+
+/// Fraction bits needed to resolve the pixels of `viewport`.
+pub fn precision_for(bounds: (usize, usize), viewport: &Viewport) -> u32 {
+    let spacing = viewport.pixel_size(bounds);
+    (-spacing.log2()).ceil().max(0.0) as u32 + GUARD_BITS
+}
+
+#[test]
+fn test_precision_for() {
+    use num::Complex;
+    let center = Complex { re: -0.75, im: 0.1 };
+    let shallow = Viewport::from_zoom(center, 1.0, 0.0).fit((100, 100));
+    assert!(!needs_precision((100, 100), &shallow));
+    assert_eq!(precision_for((100, 100), &shallow), 5 + GUARD_BITS);
+
+    let deep = Viewport::from_zoom(center, 1e100, 0.0).fit((100, 100));
+    assert!(needs_precision((100, 100), &deep));
+    assert!(precision_for((100, 100), &deep) > 332 + 6);
+}
+
+fn no_precise_form() -> Error {
+    Error::Invalid("this fractal has no arbitrary-precision form".to_string())
+}
+
+/// Like [`escape_time`](crate::render::escape_time), in arbitrary
+/// precision. Returns `Err` if the formula has no precise form.
+pub fn escape_time_precise<F: Formula>(formula: &F, z: FixedComplex, c: &FixedComplex, limit: usize, radius: f64) -> Result<Option<Escape>, Error> {
+    let mut z = z;
+    for i in 0..limit {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > radius * radius {
+            let z = z.to_complex();
+            return Ok(Some(Escape { count: i, smooth: smooth_count(i, z, radius, formula.degree()), z }));
+        }
+        z = formula.step_precise(&z, c).ok_or_else(no_precise_form)?;
+    }
+    Ok(None)
+}
+
+/// Like [`render`](crate::render::render), but iterates in fixed point with
+/// enough bits for the view. `center` is the view's center in full
+/// precision; `viewport.center` is only its nearest `f64`.
+pub fn render_precise<F: Formula + Sync>(bounds: (usize, usize), viewport: &Viewport, center: &FixedComplex, params: &Params<F>, limit: usize) -> Result<Field, Error> {
+    let bits = center.bits();
+    // Fail fast on formulas without a precise form rather than per pixel.
+    let zero = FixedComplex::zero(bits);
+    params.formula.step_precise(&zero, &zero).ok_or_else(no_precise_form)?;
+
+    let julia = match params.mode {
+        Mode::Mandelbrot => None,
+        Mode::Julia(c) => Some(FixedComplex::from_complex(c, bits)),
+    };
+
+    let mut field = Field::new(bounds, limit)?;
+    field.samples.par_chunks_mut(bounds.0)
+        .enumerate()
+        .try_for_each(|(row, band)| -> Result<(), Error> {
+            for (column, sample) in band.iter_mut().enumerate() {
+                let offset = position_to_offset(bounds, (column as f64, row as f64), viewport);
+                let point = center.add(&FixedComplex::from_complex(offset, bits));
+                *sample = match &julia {
+                    None => escape_time_precise(&params.formula, zero.clone(), &point, limit, params.radius)?,
+                    Some(c) => escape_time_precise(&params.formula, point, c, limit, params.radius)?,
+                };
+            }
+            Ok(())
+        })?;
+    Ok(field)
+}
+
+#[test]
+fn test_render_precise_matches_f64() {
+    use crate::formula::Fractal;
+    use crate::render::render;
+    use num::Complex;
+
+    let bounds = (24, 16);
+    let viewport = Viewport::from_zoom(Complex { re: -0.75, im: 0.1 }, 20.0, 0.0).fit(bounds);
+    let center = FixedComplex::from_complex(viewport.center, precision_for(bounds, &viewport) + 32);
+    for mode in [Mode::Mandelbrot, Mode::Julia(Complex { re: -0.8, im: 0.156 })] {
+        let params = Params { formula: Fractal::Mandelbrot, mode, radius: 256.0, interior_checks: true };
+        let fast = render(bounds, &viewport, &params, 200).unwrap();
+        let precise = render_precise(bounds, &viewport, &center, &params, 200).unwrap();
+        let agree = fast.samples.iter().zip(&precise.samples)
+            .filter(|(a, b)| a.map(|e| e.count) == b.map(|e| e.count))
+            .count();
+        assert!(agree >= fast.samples.len() * 95 / 100);
+    }
+}
+
+#[test]
+fn test_render_precise_resolves_deep_zoom() {
+    use crate::formula::Fractal;
+    use num::Complex;
+
+    // At 1e-20 across, f64 can't tell these pixels apart, so a plain render
+    // would be one flat block. The set's tip at -2 splits the precise one:
+    // escaping points on the left, interior points on the right.
+    let bounds = (8, 8);
+    let viewport = Viewport { center: Complex { re: -2.0, im: 0.0 }, width: 1e-20, height: 1e-20, rotation: 0.0 };
+    let center = FixedComplex::parse("-2,0", precision_for(bounds, &viewport)).unwrap();
+    let params = Params { formula: Fractal::Mandelbrot, mode: Mode::Mandelbrot, radius: 256.0, interior_checks: true };
+    let field = render_precise(bounds, &viewport, &center, &params, 2000).unwrap();
+    let middle_row = &field.samples[4 * 8..5 * 8];
+    assert!(middle_row[0].is_some());
+    assert!(middle_row[7].is_none());
+    assert!(middle_row[0].unwrap().smooth < middle_row[3].unwrap().smooth);
+}
@@ -0,0 +1,147 @@
+// The terminal preview: the view drawn in the terminal with half blocks in
+// 24-bit color, two pixels to a character cell, for machines without a
+// display.
+//
+//     arrows, hjkl    pan by an eighth of the screen
+//     + -             zoom in or out
+//     [ ]             halve or double the iteration limit
+//     p f             next palette, next fractal
+//     r               back to the starting view
+//     s               render the view at full size
+//     q, Esc, Enter   quit, printing the options for the view
+//
+// On quitting it prints the --upper-left and --lower-right options that
+// render the view at the size given with --size.
+
+use crate::view::{View, FRACTALS};
+use clap::ArgMatches;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use mandelbrot::palette::BUILTIN_NAMES;
+use mandelbrot::{Error, Image};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+// The upper half block: its foreground colors the top of a cell and its
+// background the bottom.
+const HALF_BLOCK: char = '▀';
+
+// Where exports go without --output, numbered.
+const DEFAULT_OUTPUT: &str = "preview.png";
+
+fn terminal_error(err: io::Error) -> Error {
+    Error::io("using the terminal", err)
+}
+
+// The pixels of a terminal of `columns` by `rows` characters, leaving the
+// last row for the status line.
+fn screen_bounds((columns, rows): (u16, u16)) -> (usize, usize) {
+    ((columns as usize).max(1), (rows as usize).saturating_sub(1).max(1) * 2)
+}
+
+// `image` as lines of half blocks, each line two rows of pixels, with the
+// color codes only where a color changes.
+fn half_blocks(image: &Image<[u8; 3]>) -> Vec<String> {
+    let rows: Vec<&[[u8; 3]]> = image.rows().collect();
+    rows.chunks(2)
+        .map(|pair| {
+            let mut line = String::new();
+            let mut colors = None;
+            for (x, &top) in pair[0].iter().enumerate() {
+                let bottom = pair.get(1).map_or([0; 3], |row| row[x]);
+                if colors != Some((top, bottom)) {
+                    let ([r, g, b], [br, bg, bb]) = (top, bottom);
+                    write!(line, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", r, g, b, br, bg, bb).unwrap();
+                    colors = Some((top, bottom));
+                }
+                line.push(HALF_BLOCK);
+            }
+            line.push_str("\x1b[0m");
+            line
+        })
+        .collect()
+}
+
+#[test]
+fn test_half_blocks() {
+    let image = Image::from_pixels((3, 3), vec![[1, 2, 3], [1, 2, 3], [9, 9, 9],
+                                                [4, 5, 6], [4, 5, 6], [4, 5, 6],
+                                                [7, 8, 9], [7, 8, 9], [7, 8, 9]]).unwrap();
+    let lines = half_blocks(&image);
+    assert_eq!(lines, ["\x1b[38;2;1;2;3m\x1b[48;2;4;5;6m▀▀\x1b[38;2;9;9;9m\x1b[48;2;4;5;6m▀\x1b[0m",
+                       "\x1b[38;2;7;8;9m\x1b[48;2;0;0;0m▀▀▀\x1b[0m"]);
+}
+
+// Draws the view filling the terminal, with `status`, or the view's own if
+// there is none, on the last line.
+fn draw(view: &mut View, out: &mut impl Write, status: Option<&str>) -> Result<(), Error> {
+    let columns = view.bounds().0;
+    let (lines, status) = match view.render(view.bounds()) {
+        Ok(image) => (half_blocks(&image), status.map_or_else(|| view.status(), str::to_string)),
+        Err(err) => (Vec::new(), format!("error: {}", err)),
+    };
+    queue!(out, MoveTo(0, 0), terminal::Clear(terminal::ClearType::All)).map_err(terminal_error)?;
+    for (y, line) in lines.iter().enumerate() {
+        queue!(out, MoveTo(0, y as u16)).map_err(terminal_error)?;
+        out.write_all(line.as_bytes()).map_err(terminal_error)?;
+    }
+    let status: String = status.chars().take(columns).collect();
+    queue!(out, MoveTo(0, (view.bounds().1 / 2) as u16)).map_err(terminal_error)?;
+    out.write_all(status.as_bytes()).map_err(terminal_error)?;
+    out.flush().map_err(terminal_error)
+}
+
+// Moves `view` around as keys are pressed until one quits.
+fn interact(view: &mut View, out: &mut impl Write) -> Result<(), Error> {
+    let mut status = None;
+    loop {
+        draw(view, out, status.as_deref())?;
+        status = None;
+        let bounds = view.bounds();
+        let step = (bounds.0 as f64 / 8.0, bounds.1 as f64 / 8.0);
+        match event::read().map_err(terminal_error)? {
+            Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) => match code {
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => return Ok(()),
+                KeyCode::Left | KeyCode::Char('h') => view.pan((step.0, 0.0)),
+                KeyCode::Right | KeyCode::Char('l') => view.pan((-step.0, 0.0)),
+                KeyCode::Up | KeyCode::Char('k') => view.pan((0.0, step.1)),
+                KeyCode::Down | KeyCode::Char('j') => view.pan((0.0, -step.1)),
+                KeyCode::Char('+') | KeyCode::Char('=') => view.zoom_middle(2.0),
+                KeyCode::Char('-') => view.zoom_middle(0.5),
+                KeyCode::Char('[') => view.scale_limit(0.5)?,
+                KeyCode::Char(']') => view.scale_limit(2.0)?,
+                KeyCode::Char('p') => view.cycle("palette", BUILTIN_NAMES),
+                KeyCode::Char('f') => view.cycle("fractal", FRACTALS),
+                KeyCode::Char('r') => view.reset()?,
+                KeyCode::Char('s') => {
+                    status = Some(match view.export() {
+                        Ok(filename) => format!("wrote {}", filename),
+                        Err(err) => format!("error: {}", err),
+                    });
+                }
+                _ => {}
+            },
+            Event::Resize(columns, rows) => view.resize(screen_bounds((columns, rows))),
+            _ => {}
+        }
+    }
+}
+
+// Shows the view `matches` describes in the terminal until the user quits,
+// then prints the options that render it.
+pub fn run(matches: &ArgMatches) -> Result<(), Error> {
+    let mut view = View::new(matches, screen_bounds(terminal::size().map_err(terminal_error)?), DEFAULT_OUTPUT)?;
+    let mut out = io::stdout();
+    terminal::enable_raw_mode().map_err(terminal_error)?;
+    let result = execute!(out, EnterAlternateScreen, Hide).map_err(terminal_error)
+        .and_then(|()| interact(&mut view, &mut out));
+    // Put the terminal back however the preview ended.
+    let restored = execute!(out, Show, LeaveAlternateScreen).and_then(|()| terminal::disable_raw_mode());
+    result?;
+    restored.map_err(terminal_error)?;
+    println!("{}", view.view_options()?);
+    Ok(())
+}
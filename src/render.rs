@@ -0,0 +1,343 @@
+//! Escape-time iteration and the compute and coloring stages built on it.
+
+use crate::error::Error;
+use crate::field::{Escape, Field};
+use crate::formula::Formula;
+#[cfg(test)]
+use crate::formula;
+use crate::palette::ColorMap;
+use crate::pixels::{Channel, Image};
+use crate::simd::{self, LANES};
+use crate::tile::{render_tiled, Tiling};
+use crate::viewport::Viewport;
+#[cfg(test)]
+use crate::viewport::pixel_to_point;
+use num::Complex;
+use rayon::prelude::*;
+use std::str::FromStr;
+
+/// Which plane an image shows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// The parameter plane: each pixel is `c`, and `z` starts at zero.
+    Mandelbrot,
+    /// The dynamical plane for a fixed `c`: each pixel is the starting `z`.
+    Julia(Complex<f64>),
+}
+
+impl Mode {
+    /// The starting `z` and the constant `c` for the point under a pixel.
+    pub fn start(self, point: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        match self {
+            Mode::Mandelbrot => (Complex { re: 0.0, im: 0.0 }, point),
+            Mode::Julia(c) => (point, c),
+        }
+    }
+}
+
+#[test]
+fn test_mode_start() {
+    let point = Complex { re: 0.25, im: -0.5 };
+    let c = Complex { re: -0.8, im: 0.156 };
+    assert_eq!(Mode::Mandelbrot.start(point), (Complex { re: 0.0, im: 0.0 }, point));
+    assert_eq!(Mode::Julia(c).start(point), (point, c));
+}
+
+/// The continuous iteration count for a point that escaped after `count`
+/// iterations with final value `z`, for a formula of the given degree.
+pub fn smooth_count(count: usize, z: Complex<f64>, radius: f64, degree: f64) -> f64 {
+    if degree <= 1.0 || radius <= 1.0 {
+        return count as f64;
+    }
+    let ratio = z.norm().ln() / radius.ln();
+    (count as f64 - ratio.ln() / degree.ln()).max(0.0)
+}
+
+#[test]
+fn test_smooth_count() {
+    assert_eq!(smooth_count(10, Complex { re: 256.0, im: 0.0 }, 256.0, 2.0), 10.0);
+    assert!((smooth_count(10, Complex { re: 65536.0, im: 0.0 }, 256.0, 2.0) - 9.0).abs() < 1e-12);
+    assert_eq!(smooth_count(10, Complex { re: 65536.0, im: 0.0 }, 256.0, 1.0), 10.0);
+}
+
+/// Iterates `formula` from `z` with constant `c` until |z| exceeds `radius`,
+/// giving up after `limit` iterations. Returns `None` if `z` never escaped,
+/// meaning the point is probably in the set.
+pub fn escape_time<F: Formula>(formula: &F, z: Complex<f64>, c: Complex<f64>, limit: usize, radius: f64) -> Option<Escape> {
+    iterate(formula, z, c, limit, radius, false).0
+}
+
+// Orbits returning this close to an earlier point are taken to be cycling.
+const PERIOD_TOLERANCE: f64 = 1e-24;
+
+/// Like [`escape_time`], optionally stopping early when the orbit falls into
+/// a cycle, which only points in the set do. Also returns the number of
+/// iterations run.
+///
+/// Cycles are found Brent-style: the orbit is compared against a saved point
+/// that is replaced after 1, 2, 4, 8, ... steps, so a cycle of any length is
+/// caught within a few multiples of its period.
+pub fn iterate<F: Formula>(formula: &F, z: Complex<f64>, c: Complex<f64>, limit: usize, radius: f64, periodicity: bool) -> (Option<Escape>, usize) {
+    let mut z = z;
+    let mut saved = z;
+    let mut stretch = 1;
+    let mut steps = 0;
+    for i in 0..limit {
+        if z.norm_sqr() > radius * radius {
+            return (Some(Escape { count: i, smooth: smooth_count(i, z, radius, formula.degree()), z }), i)
+        }
+        z = formula.step(z, c);
+
+        if periodicity {
+            if (z - saved).norm_sqr() < PERIOD_TOLERANCE {
+                return (None, i + 1);
+            }
+            steps += 1;
+            if steps == stretch {
+                saved = z;
+                stretch *= 2;
+                steps = 0;
+            }
+        }
+    }
+
+    (None, limit)
+}
+
+#[test]
+fn test_iterate_periodicity() {
+    let origin = Complex { re: 0.0, im: 0.0 };
+    // c = -1 cycles 0, -1, 0, -1, ... straight away.
+    let c = Complex { re: -1.0, im: 0.0 };
+    assert_eq!(iterate(&formula::Mandelbrot, origin, c, 1000, 2.0, true), (None, 3));
+    assert_eq!(iterate(&formula::Mandelbrot, origin, c, 1000, 2.0, false), (None, 1000));
+    // Escaping points are unaffected.
+    let c = Complex { re: 0.4, im: 0.6 };
+    assert_eq!(iterate(&formula::Mandelbrot, origin, c, 1000, 2.0, true),
+               iterate(&formula::Mandelbrot, origin, c, 1000, 2.0, false));
+}
+
+#[test]
+fn test_escape_time() {
+    let origin = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(escape_time(&formula::Mandelbrot, origin, origin, 255, 2.0), None);
+    let escape = escape_time(&formula::Mandelbrot, origin, Complex { re: 1.0, im: 0.0 }, 255, 2.0).unwrap();
+    assert_eq!(escape.count, 3);
+    assert!(escape.smooth > 0.0 && escape.smooth <= 3.0);
+    // Julia starting points outside the radius escape before iterating.
+    let escape = escape_time(&formula::Mandelbrot, Complex { re: 3.0, im: 0.0 }, origin, 255, 2.0).unwrap();
+    assert_eq!(escape.count, 0);
+}
+
+#[test]
+fn test_smooth_is_continuous() {
+    // Neighbouring points on either side of a count boundary get nearly
+    // equal smooth values even though their integer counts differ.
+    let origin = Complex { re: 0.0, im: 0.0 };
+    let mut previous: Option<Escape> = None;
+    for step in 0..2000 {
+        let c = Complex { re: 0.3 + step as f64 * 1e-5, im: 0.0 };
+        let escape = escape_time(&formula::Mandelbrot, origin, c, 1000, 1e6).unwrap();
+        if let Some(previous) = previous {
+            assert!((escape.smooth - previous.smooth).abs() < 0.1);
+        }
+        previous = Some(escape);
+    }
+}
+
+/// An iteration limit that grows with the zoom depth of the view, since
+/// detail near the boundary needs more iterations the deeper we look.
+pub fn auto_limit(viewport: &Viewport) -> usize {
+    let depth = (viewport.zoom() * 0.75).log10().max(0.0);
+    (250.0 * (1.0 + depth).powf(1.5)).round() as usize
+}
+
+#[test]
+fn test_auto_limit() {
+    let center = Complex { re: -0.75, im: 0.1 };
+    assert_eq!(auto_limit(&Viewport::from_zoom(center, 1.0, 0.0)), 250);
+    let deep = auto_limit(&Viewport::from_zoom(center, 1e10, 0.0));
+    assert!(deep > 5000);
+    assert!(deep < auto_limit(&Viewport::from_zoom(center, 1e14, 0.0)));
+}
+
+/// An iteration limit as given on the command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// Exactly this many iterations.
+    Fixed(usize),
+    /// Chosen from the view by [`auto_limit`].
+    Auto,
+}
+
+impl Limit {
+    /// The limit to use when rendering `viewport`.
+    pub fn resolve(self, viewport: &Viewport) -> usize {
+        match self {
+            Limit::Fixed(n) => n,
+            Limit::Auto => auto_limit(viewport),
+        }
+    }
+}
+
+impl FromStr for Limit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Limit, Error> {
+        if s == "auto" {
+            return Ok(Limit::Auto);
+        }
+        match usize::from_str(s) {
+            Ok(0) => Err(Error::Invalid("the iteration limit must be at least 1".to_string())),
+            Ok(n) => Ok(Limit::Fixed(n)),
+            Err(_) => Err(Error::Parse(format!("invalid iteration limit '{}', expected a count or 'auto'", s))),
+        }
+    }
+}
+
+#[test]
+fn test_parse_limit() {
+    assert_eq!("auto".parse().ok(), Some(Limit::Auto));
+    assert_eq!("5000000".parse().ok(), Some(Limit::Fixed(5_000_000)));
+    assert!("0".parse::<Limit>().is_err());
+    assert!("-3".parse::<Limit>().is_err());
+}
+
+/// Everything that decides how a single point iterates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Params<F> {
+    /// The map being iterated.
+    pub formula: F,
+    /// Whether pixels are `c` values or starting points.
+    pub mode: Mode,
+    /// Points escape once |z| exceeds this.
+    pub radius: f64,
+    /// Skip iterating points shown to be in the set by the cardioid and
+    /// bulb tests or by periodicity. Turn off to verify the checks.
+    pub interior_checks: bool,
+}
+
+/// Iteration counts gathered while rendering.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Iterations computed.
+    pub iterations: u64,
+    /// Iterations interior checks spared: how many more points found to be
+    /// in the set would have run before reaching the limit.
+    pub saved: u64,
+    /// Pixels copied from their neighbours by a [`Strategy`] instead of
+    /// being iterated.
+    ///
+    /// [`Strategy`]: crate::strategy::Strategy
+    pub filled: u64,
+}
+
+impl std::ops::AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.iterations += other.iterations;
+        self.saved += other.saved;
+        self.filled += other.filled;
+    }
+}
+
+impl<F: Formula> Params<F> {
+    /// Iterates the point under a pixel, up to `limit` times.
+    pub fn escape_time(&self, point: Complex<f64>, limit: usize) -> Option<Escape> {
+        self.iterate(point, limit, &mut Stats::default())
+    }
+
+    /// Like [`Params::escape_time`], adding the work done to `stats`.
+    pub fn iterate(&self, point: Complex<f64>, limit: usize, stats: &mut Stats) -> Option<Escape> {
+        let (z, c) = self.mode.start(point);
+        if self.interior_checks && self.mode == Mode::Mandelbrot && self.formula.known_interior(c) {
+            stats.saved += limit as u64;
+            return None;
+        }
+        let (escape, iterations) = iterate(&self.formula, z, c, limit, self.radius, self.interior_checks);
+        stats.iterations += iterations as u64;
+        if escape.is_none() {
+            stats.saved += (limit - iterations) as u64;
+        }
+        escape
+    }
+
+    /// [`Params::iterate`] for [`LANES`] points at once, using the vectorized
+    /// kernel when the formula allows it.
+    pub fn iterate_lanes(&self, points: [Complex<f64>; LANES], limit: usize, stats: &mut Stats) -> [Option<Escape>; LANES] {
+        if !self.formula.is_quadratic() {
+            return points.map(|point| self.iterate(point, limit, stats));
+        }
+        let (z, c) = (points.map(|point| self.mode.start(point).0), points.map(|point| self.mode.start(point).1));
+        let live = c.map(|c| !(self.interior_checks && self.mode == Mode::Mandelbrot && self.formula.known_interior(c)));
+        let outcomes = simd::iterate_lanes(z, c, live, limit, self.radius, self.interior_checks);
+        // Lanes skipped as known interior ran no iterations, so all of theirs
+        // count as saved.
+        for (escape, iterations) in &outcomes {
+            stats.iterations += *iterations as u64;
+            if escape.is_none() {
+                stats.saved += (limit - iterations) as u64;
+            }
+        }
+        outcomes.map(|(escape, _)| escape)
+    }
+}
+
+/// Computes the whole field for an image of `bounds` pixels, in tiles of the
+/// default size starting from the middle. Fails if the image has more
+/// pixels than a `usize` can count.
+pub fn render<F: Formula + Sync>(bounds: (usize, usize), viewport: &Viewport, params: &Params<F>, limit: usize) -> Result<Field, Error> {
+    Ok(render_with_stats(bounds, viewport, params, limit)?.0)
+}
+
+/// Like [`render()`], also totting up the iterations done and saved.
+pub fn render_with_stats<F: Formula + Sync>(bounds: (usize, usize), viewport: &Viewport, params: &Params<F>, limit: usize) -> Result<(Field, Stats), Error> {
+    let tiled = render_tiled(bounds, viewport, params, limit, &Tiling::default(), |_, _| true)?;
+    Ok((tiled.field, tiled.stats))
+}
+
+#[test]
+fn test_render() {
+    let params = Params { formula: formula::Mandelbrot, mode: Mode::Mandelbrot, radius: 2.0, interior_checks: true };
+    let viewport = Viewport::from_corners(Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 });
+    let field = render((30, 20), &viewport, &params, 100).unwrap();
+    assert_eq!(field.bounds(), (30, 20));
+    // The upper left corner is well outside the set, its middle inside.
+    assert!(field.samples[0].is_some());
+    assert!(field.samples[10 * 30 + 15].is_none());
+    assert_eq!(field.samples[31], params.escape_time(pixel_to_point((30, 20), (1, 1), &viewport), 100));
+}
+
+#[test]
+fn test_interior_checks() {
+    let checked = Params { formula: formula::Fractal::Mandelbrot, mode: Mode::Mandelbrot, radius: 256.0, interior_checks: true };
+    let unchecked = Params { interior_checks: false, ..checked.clone() };
+    let bounds = (60, 40);
+    let viewport = Viewport::from_zoom(Complex { re: -0.61, im: 0.013 }, 1.5, 0.0).fit(bounds);
+
+    let (fast, fast_stats) = render_with_stats(bounds, &viewport, &checked, 1000).unwrap();
+    let (slow, slow_stats) = render_with_stats(bounds, &viewport, &unchecked, 1000).unwrap();
+    assert_eq!(fast, slow);
+    assert_eq!(slow_stats.saved, 0);
+    assert!(fast_stats.saved > slow_stats.iterations / 2);
+    assert_eq!(fast_stats.iterations + fast_stats.saved, slow_stats.iterations);
+}
+
+fn colorize_band<C: Channel>(pixels: &mut [[C; 3]], samples: &[Option<Escape>], colors: &ColorMap) {
+    for (pixel, sample) in pixels.iter_mut().zip(samples) {
+        *pixel = colors.color_as(sample.map(|e| e.smooth));
+    }
+}
+
+/// Maps every sample of `field` to a color.
+pub fn colorize(field: &Field, colors: &ColorMap) -> Image<[u8; 3]> {
+    colorize_as(field, colors)
+}
+
+/// Like [`colorize`], with channels of type `C`.
+pub fn colorize_as<C: Channel>(field: &Field, colors: &ColorMap) -> Image<[C; 3]> {
+    let mut image = Image::like(&field.samples);
+    image.par_rows_mut()
+        .zip(field.samples.par_chunks(field.samples.width().max(1)))
+        .for_each(|(row, samples)| colorize_band(row, samples, colors));
+    image
+}
@@ -0,0 +1,384 @@
+//! Keyframed animations: scene files giving the view and coloring at chosen
+//! frames, with the frames between interpolated.
+//!
+//! A scene is a TOML file of `[[keyframe]]` tables:
+//!
+//! ```toml
+//! [[keyframe]]
+//! frame = 0
+//! center = "-0.5,0"
+//! zoom = 1
+//! julia = "-0.8,0.156"
+//! interpolation = "cubic"
+//!
+//! [[keyframe]]
+//! frame = 100
+//! rotation = 90
+//! offset = 0.5
+//! julia = "-0.7,0.27"
+//! ```
+//!
+//! Each keyframe may set `center`, `zoom`, `rotation` (degrees), `max_iter`,
+//! `offset` (of the palette) and `julia`; anything left out keeps the
+//! previous keyframe's value. Centers keep every digit given, for zooms
+//! too deep for an `f64`; see [`Scene::precise_center`]. `interpolation`
+//! picks how the frames up to the next keyframe are filled in: see
+//! [`Interpolation`].
+
+use crate::animation::{Easing, ZoomPath};
+use crate::error::Error;
+use crate::fixed::FixedComplex;
+use crate::parse_complex;
+use crate::viewport::Viewport;
+use num::Complex;
+use serde::Deserialize;
+use std::fs;
+
+/// How values change between one keyframe and the next.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interpolation {
+    /// Every value, zoom included, changes at a constant rate.
+    #[default]
+    Linear,
+    /// A Catmull–Rom spline through the neighbouring keyframes, so motion
+    /// doesn't jerk at keyframes; zoom follows the spline in log space.
+    Cubic,
+    /// Zoom changes by the same factor every frame and the center drifts
+    /// onto the next keyframe's in step, as in a [`ZoomPath`]; other values
+    /// change at a constant rate.
+    LogZoom,
+}
+
+/// The values a keyframe sets, or that a frame is rendered with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    /// The point at the middle of the image.
+    pub center: Complex<f64>,
+    /// Magnification, in the sense of [`Viewport::zoom`].
+    pub zoom: f64,
+    /// Counter-clockwise turn in degrees.
+    pub rotation: f64,
+    /// Iteration limit, or `None` to leave it to the renderer.
+    pub max_iter: Option<usize>,
+    /// Palette offset, as in [`ColorMap::offset`](crate::ColorMap::offset).
+    pub offset: f64,
+    /// Julia constant, or `None` for the parameter plane.
+    pub julia: Option<Complex<f64>>,
+}
+
+impl Frame {
+    /// A square view of this frame, before fitting to the image.
+    pub fn viewport(&self) -> Viewport {
+        Viewport::from_zoom(self.center, self.zoom, self.rotation.to_radians())
+    }
+}
+
+/// A keyframe: the values at `frame`, and how to move on from them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    /// Frame number, counting from zero.
+    pub frame: usize,
+    /// Values at that frame.
+    pub values: Frame,
+    /// The center as written, if it was, with the digits `values.center`
+    /// rounds away.
+    pub center: Option<String>,
+    /// How to reach the next keyframe.
+    pub interpolation: Interpolation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeFile {
+    frame: usize,
+    center: Option<String>,
+    zoom: Option<f64>,
+    rotation: Option<f64>,
+    max_iter: Option<usize>,
+    offset: Option<f64>,
+    julia: Option<String>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    keyframe: Vec<KeyframeFile>,
+}
+
+/// A keyframed animation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    /// Keyframes in order of frame number, at least one.
+    pub keyframes: Vec<Keyframe>,
+}
+
+fn parse_point(s: &str) -> Result<Complex<f64>, Error> {
+    match parse_complex(s) {
+        Some(point) if point.re.is_finite() && point.im.is_finite() => Ok(point),
+        Some(_) => Err(Error::Parse(format!("point '{}' isn't finite", s))),
+        None => Err(Error::Parse(format!("invalid point '{}', expected RE,IM", s))),
+    }
+}
+
+impl Scene {
+    /// Parses a scene file. Values the first keyframe leaves out come from
+    /// `defaults`, with `default_center` the center as written if it was.
+    pub fn parse(text: &str, defaults: &Frame, default_center: Option<&str>) -> Result<Scene, Error> {
+        let file: SceneFile = toml::from_str(text).map_err(|err| Error::Parse(err.to_string().trim_end().to_string()))?;
+        let mut keyframes: Vec<Keyframe> = Vec::new();
+        for key in file.keyframe {
+            let previous = keyframes.last().map_or(defaults, |keyframe| &keyframe.values);
+            let center = match &key.center {
+                Some(center) => Some(center.clone()),
+                None => keyframes.last().map_or(default_center.map(str::to_string), |keyframe| keyframe.center.clone()),
+            };
+            if keyframes.last().is_some_and(|last| last.frame >= key.frame) {
+                return Err(Error::Parse(format!("keyframe at frame {} is out of order", key.frame)));
+            }
+            let values = Frame {
+                center: key.center.as_deref().map(parse_point).transpose()?.unwrap_or(previous.center),
+                zoom: key.zoom.unwrap_or(previous.zoom),
+                rotation: key.rotation.unwrap_or(previous.rotation),
+                max_iter: key.max_iter.or(previous.max_iter),
+                offset: key.offset.unwrap_or(previous.offset),
+                julia: key.julia.as_deref().map(parse_point).transpose()?.or(previous.julia),
+            };
+            if !(values.zoom.is_finite() && values.zoom > 0.0) {
+                return Err(Error::Parse(format!("zoom must be a positive number, not {}", values.zoom)));
+            }
+            if !values.rotation.is_finite() {
+                return Err(Error::Parse(format!("rotation must be a finite number, not {}", values.rotation)));
+            }
+            if !values.offset.is_finite() {
+                return Err(Error::Parse(format!("offset must be a finite number, not {}", values.offset)));
+            }
+            if values.max_iter == Some(0) {
+                return Err(Error::Parse("iteration limit must be at least 1".to_string()));
+            }
+            if let Some(last) = keyframes.last() {
+                if last.values.julia.is_some() != values.julia.is_some() || last.values.max_iter.is_some() != values.max_iter.is_some() {
+                    return Err(Error::Parse(format!("keyframe at frame {} sets julia or max_iter, which earlier keyframes must set too",
+                                                   key.frame)));
+                }
+            }
+            keyframes.push(Keyframe { frame: key.frame, values, center, interpolation: key.interpolation });
+        }
+        if keyframes.is_empty() {
+            return Err(Error::Parse("scene has no keyframes".to_string()));
+        }
+        Ok(Scene { keyframes })
+    }
+
+    /// Reads and parses the scene file at `path`.
+    pub fn load(path: &str, defaults: &Frame, default_center: Option<&str>) -> Result<Scene, Error> {
+        let text = fs::read_to_string(path).map_err(|err| Error::io(format!("reading scene {}", path), err))?;
+        Scene::parse(&text, defaults, default_center).map_err(|err| Error::Parse(format!("scene {}: {}", path, err)))
+    }
+
+    /// The number of frames, up to and including the last keyframe.
+    pub fn frames(&self) -> usize {
+        self.keyframes[self.keyframes.len() - 1].frame + 1
+    }
+
+    // The keyframes frame `n` lies between, or the one it holds still on.
+    fn neighbours(&self, n: usize) -> (usize, Option<usize>) {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.frame <= n);
+        if next == 0 {
+            return (0, None);
+        }
+        if next == self.keyframes.len() || n == self.keyframes[next - 1].frame {
+            return (next - 1, None);
+        }
+        (next - 1, Some(next))
+    }
+
+    /// The values at frame `n`. Frames before the first keyframe or after
+    /// the last hold still.
+    pub fn frame(&self, n: usize) -> Frame {
+        let (a, next) = match self.neighbours(n) {
+            (a, None) => return self.keyframes[a].values,
+            (a, Some(next)) => (&self.keyframes[a], next),
+        };
+        let b = &self.keyframes[next];
+        let t = (n - a.frame) as f64 / (b.frame - a.frame) as f64;
+        match a.interpolation {
+            Interpolation::Linear => {
+                interpolate(&[a.values, a.values, b.values, b.values], |_, p1, p2, _| p1 + (p2 - p1) * t)
+            }
+            Interpolation::Cubic => {
+                let before = self.keyframes[next.saturating_sub(2)].values;
+                let after = self.keyframes[(next + 1).min(self.keyframes.len() - 1)].values;
+                let mut frame = interpolate(&[before, a.values, b.values, after], |p0, p1, p2, p3| catmull_rom(p0, p1, p2, p3, t));
+                let zooms = [before.zoom, a.values.zoom, b.values.zoom, after.zoom].map(f64::ln);
+                frame.zoom = catmull_rom(zooms[0], zooms[1], zooms[2], zooms[3], t).exp();
+                frame
+            }
+            Interpolation::LogZoom => {
+                let mut frame = interpolate(&[a.values, a.values, b.values, b.values], |_, p1, p2, _| p1 + (p2 - p1) * t);
+                let frames = b.frame - a.frame + 1;
+                let path = ZoomPath {
+                    start: a.values.viewport(),
+                    offset: a.values.center - b.values.center,
+                    end_zoom: b.values.zoom,
+                    frames,
+                    easing: Easing::Linear,
+                };
+                frame.center = path.viewport(n - a.frame, b.values.center).center;
+                frame.zoom = a.values.zoom / path.scale(n - a.frame);
+                frame
+            }
+        }
+    }
+
+    /// The center of frame `n` with `bits` fraction bits. Frames are placed
+    /// by their offset from the deeper of the keyframes either side, which
+    /// an `f64` holds to well under a pixel, so deep scenes keep the
+    /// precision their centers are written with.
+    pub fn precise_center(&self, n: usize, bits: u32) -> FixedComplex {
+        let precise = |keyframe: &Keyframe| keyframe.center.as_deref()
+            .and_then(|text| FixedComplex::parse(text, bits))
+            .unwrap_or_else(|| FixedComplex::from_complex(keyframe.values.center, bits));
+        let anchor = match self.neighbours(n) {
+            (a, None) => return precise(&self.keyframes[a]),
+            (a, Some(b)) if self.keyframes[a].values.zoom >= self.keyframes[b].values.zoom => precise(&self.keyframes[a]),
+            (_, Some(b)) => precise(&self.keyframes[b]),
+        };
+        let offsets = Scene {
+            keyframes: self.keyframes.iter()
+                .map(|keyframe| Keyframe {
+                    values: Frame { center: precise(keyframe).sub(&anchor).to_complex(), ..keyframe.values },
+                    center: None,
+                    ..keyframe.clone()
+                })
+                .collect(),
+        };
+        anchor.add(&FixedComplex::from_complex(offsets.frame(n).center, bits))
+    }
+}
+
+// Catmull–Rom spline through `p1` at t = 0 and `p2` at t = 1.
+fn catmull_rom(p0: f64, p1: f64, p2: f64, p3: f64, t: f64) -> f64 {
+    0.5 * (2.0 * p1 + (p2 - p0) * t + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+           + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t)
+}
+
+// Applies `mix` to each value of four consecutive keyframes, the middle two
+// being the ones the frame lies between. Zoom goes through `mix` as is.
+fn interpolate(values: &[Frame; 4], mix: impl Fn(f64, f64, f64, f64) -> f64) -> Frame {
+    let field = |get: &dyn Fn(&Frame) -> f64| mix(get(&values[0]), get(&values[1]), get(&values[2]), get(&values[3]));
+    let point = |get: &dyn Fn(&Frame) -> Complex<f64>| Complex {
+        re: field(&|frame| get(frame).re),
+        im: field(&|frame| get(frame).im),
+    };
+    let zero = Complex { re: 0.0, im: 0.0 };
+    Frame {
+        center: point(&|frame| frame.center),
+        zoom: field(&|frame| frame.zoom),
+        rotation: field(&|frame| frame.rotation),
+        max_iter: values[1].max_iter.map(|_| field(&|frame| frame.max_iter.unwrap_or(0) as f64).round().max(1.0) as usize),
+        offset: field(&|frame| frame.offset),
+        julia: values[1].julia.map(|_| point(&|frame| frame.julia.unwrap_or(zero))),
+    }
+}
+
+#[cfg(test)]
+const DEFAULTS: Frame = Frame {
+    center: Complex { re: -0.5, im: 0.0 },
+    zoom: 1.0,
+    rotation: 0.0,
+    max_iter: None,
+    offset: 0.0,
+    julia: None,
+};
+
+#[test]
+fn test_scene_parse() {
+    let scene = Scene::parse("
+        [[keyframe]]
+        frame = 0
+        max_iter = 100
+        julia = \"-0.8,0.156\"
+
+        [[keyframe]]
+        frame = 10
+        zoom = 4.0
+        interpolation = \"log-zoom\"
+    ", &DEFAULTS, None).unwrap();
+    assert_eq!(scene.frames(), 11);
+    assert_eq!(scene.keyframes[0].values, Frame { max_iter: Some(100), julia: Some(Complex { re: -0.8, im: 0.156 }), ..DEFAULTS });
+    assert_eq!(scene.keyframes[1].values, Frame { zoom: 4.0, ..scene.keyframes[0].values });
+    assert_eq!(scene.keyframes[1].interpolation, Interpolation::LogZoom);
+
+    assert!(Scene::parse("", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\nspeed = 2", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\ncenter = \"1\"", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 3\n[[keyframe]]\nframe = 3", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\n[[keyframe]]\nframe = 3\nmax_iter = 50", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\nzoom = -1", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\nrotation = nan", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\nrotation = -inf", &DEFAULTS, None).is_err());
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\noffset = inf", &DEFAULTS, None).is_err());
+}
+
+#[test]
+fn test_scene_interpolation() {
+    let keyframe = |frame, values, interpolation| Keyframe { frame, values, center: None, interpolation };
+    let a = Frame { max_iter: Some(100), julia: Some(Complex { re: -0.8, im: 0.0 }), ..DEFAULTS };
+    let b = Frame { center: Complex { re: 0.5, im: 1.0 }, zoom: 100.0, rotation: 90.0, max_iter: Some(300), offset: 1.0,
+                    julia: Some(Complex { re: -0.6, im: 0.2 }) };
+    let c = Frame { zoom: 10.0, ..b };
+
+    for interpolation in [Interpolation::Linear, Interpolation::Cubic, Interpolation::LogZoom] {
+        let scene = Scene { keyframes: vec![keyframe(0, a, interpolation), keyframe(10, b, interpolation), keyframe(20, c, interpolation)] };
+        // Keyframes are hit exactly, and the ends hold still.
+        assert_eq!(scene.frame(0), a);
+        assert_eq!(scene.frame(10), b);
+        assert_eq!(scene.frame(20), c);
+        assert_eq!(scene.frame(25), c);
+        let middle = scene.frame(5);
+        assert!(middle.zoom > 1.0 && middle.zoom < 100.0);
+        assert!(middle.rotation > 0.0 && middle.rotation < 90.0);
+        assert_eq!(middle.max_iter, Some(200));
+    }
+
+    let linear = Scene { keyframes: vec![keyframe(0, a, Interpolation::Linear), keyframe(10, b, Interpolation::Linear)] };
+    let middle = linear.frame(5);
+    assert_eq!((middle.center, middle.zoom, middle.offset), (Complex { re: 0.0, im: 0.5 }, 50.5, 0.5));
+    assert!((middle.julia.unwrap() - Complex { re: -0.7, im: 0.1 }).norm() < 1e-12);
+
+    let log = Scene { keyframes: vec![keyframe(0, a, Interpolation::LogZoom), keyframe(10, b, Interpolation::LogZoom)] };
+    assert!((log.frame(5).zoom - 10.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_scene_precise_center() {
+    let scene = Scene::parse("
+        [[keyframe]]
+        frame = 0
+        center = \"-1.00000000000000000001,0\"
+        zoom = 1e20
+
+        [[keyframe]]
+        frame = 10
+        center = \"-1.00000000000000000003,0.00000000000000000001\"
+
+        [[keyframe]]
+        frame = 20
+        zoom = 1
+    ", &DEFAULTS, Some("0.5,0")).unwrap();
+    assert_eq!(scene.keyframes[2].center.as_deref(), Some("-1.00000000000000000003,0.00000000000000000001"));
+    let exact = |text| FixedComplex::parse(text, 128).unwrap();
+    assert_eq!(scene.precise_center(0, 128), exact("-1.00000000000000000001,0"));
+    assert_eq!(scene.precise_center(25, 128), exact("-1.00000000000000000003,0.00000000000000000001"));
+    // All three would be -1 in an f64.
+    let middle = scene.precise_center(5, 128).sub(&exact("-1.00000000000000000002,0.000000000000000000005"));
+    assert!(middle.to_complex().norm() < 1e-30);
+    assert!((scene.precise_center(15, 128).to_complex() - scene.frame(15).center).norm() < 1e-15);
+
+    let scene = Scene::parse("[[keyframe]]\nframe = 0", &DEFAULTS, Some("0.10000000000000000001,0")).unwrap();
+    assert_eq!(scene.precise_center(0, 128), exact("0.10000000000000000001,0"));
+    assert!(Scene::parse("[[keyframe]]\nframe = 0\ncenter = \"inf,0\"", &DEFAULTS, None).is_err());
+}
@@ -0,0 +1,252 @@
+//! Shortcuts that skip iterating pixels inside regions of equal iteration
+//! count.
+//!
+//! Both work on one rectangle of samples at a time, asking `evaluate` for the
+//! pixels they need and copying the rest from their neighbours. Neither is
+//! exact: a feature small enough to fall entirely inside a rectangle, or an
+//! island that never touches a traced boundary, is painted over. Filled
+//! pixels copy their smooth count as well, so smooth coloring shows them as
+//! flat patches.
+
+use crate::error::Error;
+use crate::field::Escape;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+/// How to decide which pixels of a tile to iterate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Iterate every pixel.
+    BruteForce,
+    /// Iterate the border of a rectangle; fill it if the border is all one
+    /// count, otherwise split it in two and try again.
+    MarianiSilver,
+    /// Follow the edges between regions of different counts and flood fill
+    /// what they enclose.
+    BoundaryTrace,
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Strategy, Error> {
+        match s {
+            "brute-force" => Ok(Strategy::BruteForce),
+            "mariani-silver" => Ok(Strategy::MarianiSilver),
+            "boundary-trace" => Ok(Strategy::BoundaryTrace),
+            _ => Err(Error::Parse(format!("unknown strategy '{}', expected brute-force, mariani-silver or boundary-trace", s))),
+        }
+    }
+}
+
+// What regions are made of: the iteration count, or `None` inside the set.
+fn count(sample: &Option<Escape>) -> Option<usize> {
+    sample.map(|escape| escape.count)
+}
+
+// The height of `samples`, a rectangle `width` pixels wide, unless they don't
+// make whole rows.
+fn height(samples: &[Option<Escape>], width: usize) -> Result<usize, Error> {
+    if !samples.len().is_multiple_of(width) {
+        return Err(Error::Invalid(format!("{} samples can't make rows {} pixels wide", samples.len(), width)));
+    }
+    Ok(samples.len().checked_div(width).unwrap_or(0))
+}
+
+// Rectangles this small are iterated outright rather than split again.
+const MIN_SIDE: usize = 4;
+
+// Boundaries are traced from every row and column this many pixels apart as
+// well as from the edges, so regions that touch no edge, like the bands
+// circling the set, are still found.
+const SEED_SPACING: usize = 16;
+
+/// Fills `samples`, a rectangle `width` pixels wide in row-major order, by
+/// Mariani–Silver subdivision, calling `evaluate` with the index of each
+/// pixel it needs iterated. Returns the number of pixels filled without
+/// iterating, or fails if `samples` doesn't make whole rows.
+pub fn mariani_silver<E>(samples: &mut [Option<Escape>], width: usize, mut evaluate: E) -> Result<u64, Error>
+    where E: FnMut(usize) -> Option<Escape>
+{
+    let height = height(samples, width)?;
+    let mut done = vec![false; samples.len()];
+    let mut filled = 0;
+    // Rectangles as (left, top, width, height), sharing their edges with
+    // their neighbours so no pixel is iterated twice.
+    let mut stack = vec![(0, 0, width, height)];
+    while let Some((left, top, w, h)) = stack.pop() {
+        if w == 0 || h == 0 {
+            continue;
+        }
+        let mut visit = |x: usize, y: usize| {
+            let i = y * width + x;
+            if !done[i] {
+                samples[i] = evaluate(i);
+                done[i] = true;
+            }
+            count(&samples[i])
+        };
+
+        if w <= MIN_SIDE || h <= MIN_SIDE {
+            for y in top..top + h {
+                for x in left..left + w {
+                    visit(x, y);
+                }
+            }
+            continue;
+        }
+
+        let (right, bottom) = (left + w - 1, top + h - 1);
+        let first = visit(left, top);
+        let mut uniform = true;
+        for x in left..=right {
+            uniform &= visit(x, top) == first;
+            uniform &= visit(x, bottom) == first;
+        }
+        for y in top..=bottom {
+            uniform &= visit(left, y) == first;
+            uniform &= visit(right, y) == first;
+        }
+
+        if uniform {
+            let fill = samples[top * width + left];
+            for y in top + 1..bottom {
+                for x in left + 1..right {
+                    let i = y * width + x;
+                    if !done[i] {
+                        samples[i] = fill;
+                        done[i] = true;
+                        filled += 1;
+                    }
+                }
+            }
+        } else if w >= h {
+            let middle = w / 2;
+            stack.push((left, top, middle + 1, h));
+            stack.push((left + middle, top, w - middle, h));
+        } else {
+            let middle = h / 2;
+            stack.push((left, top, w, middle + 1));
+            stack.push((left, top + middle, w, h - middle));
+        }
+    }
+    Ok(filled)
+}
+
+/// Fills `samples`, a rectangle `width` pixels wide in row-major order, by
+/// tracing the boundaries between regions of equal count, calling
+/// `evaluate` with the index of each pixel it needs iterated. Returns the
+/// number of pixels filled without iterating, or fails if `samples` doesn't
+/// make whole rows.
+///
+/// This is Joel Yliluoma's queue-based tracer: starting from the rectangle's
+/// edges and a sparse grid of lines across it, every pixel that differs
+/// from a neighbour queues that neighbour, so the queue creeps along each
+/// boundary and never enters a region's inside. A sweep then copies each
+/// unvisited pixel from its left neighbour.
+pub fn boundary_trace<E>(samples: &mut [Option<Escape>], width: usize, mut evaluate: E) -> Result<u64, Error>
+    where E: FnMut(usize) -> Option<Escape>
+{
+    let height = height(samples, width)?;
+    let mut loaded = vec![false; samples.len()];
+    let mut queued = vec![false; samples.len()];
+    let mut queue = VecDeque::new();
+
+    let mut enqueue = |queue: &mut VecDeque<usize>, i: usize| {
+        if !queued[i] {
+            queued[i] = true;
+            queue.push_back(i);
+        }
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let seed = x % SEED_SPACING == 0 || y % SEED_SPACING == 0 || x + 1 == width || y + 1 == height;
+            if seed {
+                enqueue(&mut queue, y * width + x);
+            }
+        }
+    }
+
+    while let Some(i) = queue.pop_front() {
+        let mut load = |i: usize| {
+            if !loaded[i] {
+                samples[i] = evaluate(i);
+                loaded[i] = true;
+            }
+            count(&samples[i])
+        };
+        let (x, y) = (i % width, i / width);
+        let center = load(i);
+        let (has_left, has_right, has_up, has_down) = (x > 0, x + 1 < width, y > 0, y + 1 < height);
+        let left = has_left && load(i - 1) != center;
+        let right = has_right && load(i + 1) != center;
+        let up = has_up && load(i - width) != center;
+        let down = has_down && load(i + width) != center;
+
+        for (neighbour, differs) in [(i.wrapping_sub(1), left), (i + 1, right),
+                                     (i.wrapping_sub(width), up), (i + width, down)] {
+            if differs {
+                enqueue(&mut queue, neighbour);
+            }
+        }
+        // Diagonals next to a boundary, so it can turn corners.
+        if has_up && has_left && (left || up) {
+            enqueue(&mut queue, i - width - 1);
+        }
+        if has_up && has_right && (right || up) {
+            enqueue(&mut queue, i - width + 1);
+        }
+        if has_down && has_left && (left || down) {
+            enqueue(&mut queue, i + width - 1);
+        }
+        if has_down && has_right && (right || down) {
+            enqueue(&mut queue, i + width + 1);
+        }
+    }
+
+    // The left column is all loaded, so every row fills from its start.
+    let mut filled = 0;
+    for i in 1..samples.len() {
+        if !loaded[i] {
+            samples[i] = samples[i - 1];
+            loaded[i] = true;
+            filled += 1;
+        }
+    }
+    Ok(filled)
+}
+
+#[test]
+fn test_strategies_match_brute_force() {
+    use crate::{pixel_to_point, Fractal, Mode, Params, Viewport};
+    use num::Complex;
+
+    let params = Params { formula: Fractal::Mandelbrot, mode: Mode::Mandelbrot, radius: 256.0, interior_checks: true };
+    for (center, zoom) in [((-0.5, 0.0), 1.0), ((-0.745, 0.113), 60.0)] {
+        let bounds = (120, 90);
+        let viewport = Viewport::from_zoom(Complex { re: center.0, im: center.1 }, zoom, 0.0).fit(bounds);
+        let evaluate = |i: usize| params.escape_time(pixel_to_point(bounds, (i % bounds.0, i / bounds.0), &viewport), 300);
+        let brute: Vec<Option<Escape>> = (0..bounds.0 * bounds.1).map(evaluate).collect();
+
+        let mut evaluated = 0;
+        let mut samples = vec![None; brute.len()];
+        let filled = mariani_silver(&mut samples, bounds.0, |i| { evaluated += 1; evaluate(i) }).unwrap();
+        assert_eq!(evaluated + filled as usize, brute.len());
+        assert!(filled > 1000);
+        let same = samples.iter().zip(&brute).filter(|(a, b)| count(a) == count(b)).count();
+        assert!(same * 1000 >= brute.len() * 995);
+
+        let mut evaluated = 0;
+        let mut samples = vec![None; brute.len()];
+        let filled = boundary_trace(&mut samples, bounds.0, |i| { evaluated += 1; evaluate(i) }).unwrap();
+        assert_eq!(evaluated + filled as usize, brute.len());
+        assert!(filled > 1000);
+        let same = samples.iter().zip(&brute).filter(|(a, b)| count(a) == count(b)).count();
+        assert!(same * 1000 >= brute.len() * 995);
+    }
+
+    let mut ragged = vec![None; 10];
+    assert!(matches!(mariani_silver(&mut ragged, 0, |_| None), Err(Error::Invalid(_))));
+    assert!(matches!(boundary_trace(&mut ragged, 3, |_| None), Err(Error::Invalid(_))));
+    assert_eq!(mariani_silver(&mut [], 0, |_| None).unwrap(), 0);
+}
@@ -0,0 +1,335 @@
+//! Splitting a render into square tiles and handing them out to worker
+//! threads.
+//!
+//! Tiles keep each worker's samples close together, and because they finish
+//! one at a time a render can be shown as it fills in, or abandoned partway
+//! through; see [`render_tiled`].
+
+use crate::error::Error;
+use crate::field::{Escape, Field};
+use crate::formula::Formula;
+use crate::pixels::Image;
+use crate::render::{Params, Stats};
+use crate::simd::LANES;
+use crate::strategy::{boundary_trace, mariani_silver, Strategy};
+use crate::viewport::{pixel_to_point, Viewport};
+use rayon::prelude::*;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[cfg(test)]
+use num::Complex;
+
+/// A rectangle of pixels within an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    /// Column of the leftmost pixels.
+    pub left: usize,
+    /// Row of the topmost pixels.
+    pub top: usize,
+    /// Width in pixels.
+    pub width: usize,
+    /// Height in pixels.
+    pub height: usize,
+}
+
+impl Tile {
+    /// Number of pixels the tile covers.
+    pub fn area(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// The image coordinates of the `i`th pixel of the tile, in row-major
+    /// order.
+    pub fn pixel(&self, i: usize) -> (usize, usize) {
+        (self.left + i % self.width, self.top + i / self.width)
+    }
+}
+
+/// Splits an image of `bounds` pixels into tiles `size` pixels square, in
+/// row-major order. Tiles along the right and bottom edges are cut short.
+pub fn tiles(bounds: (usize, usize), size: usize) -> Result<Vec<Tile>, Error> {
+    if size == 0 {
+        return Err(Error::Invalid("tile size must be at least 1".to_string()));
+    }
+    let mut tiles = Vec::new();
+    for top in (0..bounds.1).step_by(size) {
+        for left in (0..bounds.0).step_by(size) {
+            tiles.push(Tile {
+                left,
+                top,
+                width: size.min(bounds.0 - left),
+                height: size.min(bounds.1 - top),
+            });
+        }
+    }
+    Ok(tiles)
+}
+
+#[test]
+fn test_tiles() {
+    assert!(matches!(tiles((100, 50), 0), Err(Error::Invalid(_))));
+    let tiles = tiles((100, 50), 32).unwrap();
+    assert_eq!(tiles.len(), 4 * 2);
+    assert_eq!(tiles[0], Tile { left: 0, top: 0, width: 32, height: 32 });
+    assert_eq!(tiles[7], Tile { left: 96, top: 32, width: 4, height: 18 });
+    assert_eq!(tiles.iter().map(Tile::area).sum::<usize>(), 100 * 50);
+    assert_eq!(tiles[5].pixel(33), (33, 33));
+}
+
+/// The order tiles are handed out in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Nearest the middle of the image first, which is usually what the
+    /// viewer is looking at.
+    Center,
+    /// Most expensive first, judging by a few probe points per tile, so no
+    /// thread is left with a slow tile at the end.
+    Cost,
+    /// Top to bottom, left to right.
+    Rows,
+}
+
+impl FromStr for TileOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TileOrder, Error> {
+        match s {
+            "center" => Ok(TileOrder::Center),
+            "cost" => Ok(TileOrder::Cost),
+            "rows" => Ok(TileOrder::Rows),
+            _ => Err(Error::Parse(format!("unknown tile order '{}', expected center, cost or rows", s))),
+        }
+    }
+}
+
+/// How to split up a render.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tiling {
+    /// Edge length of the tiles in pixels.
+    pub size: usize,
+    /// Which tiles to compute first.
+    pub order: TileOrder,
+    /// Which pixels within each tile to iterate.
+    pub strategy: Strategy,
+}
+
+impl Default for Tiling {
+    fn default() -> Tiling {
+        Tiling { size: 64, order: TileOrder::Center, strategy: Strategy::BruteForce }
+    }
+}
+
+// Iterations needed for the corners and middle of a tile.
+fn estimate_cost<F: Formula>(tile: &Tile, bounds: (usize, usize), viewport: &Viewport, params: &Params<F>, limit: usize) -> u64 {
+    let (right, bottom) = (tile.left + tile.width - 1, tile.top + tile.height - 1);
+    let probes = [
+        (tile.left, tile.top),
+        (right, tile.top),
+        (tile.left, bottom),
+        (right, bottom),
+        (tile.left + tile.width / 2, tile.top + tile.height / 2),
+    ];
+    let mut stats = Stats::default();
+    for pixel in probes {
+        params.iterate(pixel_to_point(bounds, pixel, viewport), limit, &mut stats);
+    }
+    stats.iterations
+}
+
+/// Sorts `tiles` of an image of `bounds` pixels into `order`.
+pub fn schedule<F: Formula + Sync>(tiles: &mut [Tile], order: TileOrder, bounds: (usize, usize), viewport: &Viewport,
+                                   params: &Params<F>, limit: usize) {
+    match order {
+        TileOrder::Center => {
+            // Doubled coordinates keep the midpoints whole.
+            let distance = |tile: &Tile| {
+                let dx = (2 * tile.left + tile.width).abs_diff(bounds.0);
+                let dy = (2 * tile.top + tile.height).abs_diff(bounds.1);
+                dx * dx + dy * dy
+            };
+            tiles.sort_by_key(distance);
+        }
+        TileOrder::Cost => {
+            let costs: Vec<u64> = tiles.par_iter()
+                .map(|tile| estimate_cost(tile, bounds, viewport, params, limit))
+                .collect();
+            let mut ranked: Vec<(u64, Tile)> = costs.into_iter().zip(tiles.iter().copied()).collect();
+            ranked.sort_by_key(|&(cost, _)| std::cmp::Reverse(cost));
+            for (tile, (_, ranked)) in tiles.iter_mut().zip(ranked) {
+                *tile = ranked;
+            }
+        }
+        TileOrder::Rows => {}
+    }
+}
+
+#[test]
+fn test_schedule() {
+    let params = Params { formula: crate::Fractal::Mandelbrot, mode: crate::Mode::Mandelbrot, radius: 2.0, interior_checks: false };
+    let bounds = (90, 60);
+    let viewport = Viewport::from_zoom(Complex { re: -0.5, im: 0.0 }, 1.0, 0.0).fit(bounds);
+
+    let mut center = tiles(bounds, 30).unwrap();
+    schedule(&mut center, TileOrder::Center, bounds, &viewport, &params, 100);
+    assert_eq!(center[..2], [Tile { left: 30, top: 0, width: 30, height: 30 },
+                             Tile { left: 30, top: 30, width: 30, height: 30 }]);
+
+    let mut cost = tiles(bounds, 30).unwrap();
+    schedule(&mut cost, TileOrder::Cost, bounds, &viewport, &params, 100);
+    let costs: Vec<u64> = cost.iter().map(|tile| estimate_cost(tile, bounds, &viewport, &params, 100)).collect();
+    assert!(costs.windows(2).all(|pair| pair[0] >= pair[1]));
+    assert!(costs[0] > costs[5]);
+
+    let mut rows = tiles(bounds, 30).unwrap();
+    schedule(&mut rows, TileOrder::Rows, bounds, &viewport, &params, 100);
+    assert_eq!(rows, tiles(bounds, 30).unwrap());
+}
+
+/// Iterates the pixels of `tile` in an image of `bounds` pixels, or those
+/// `strategy` picks, storing them in `samples` in row-major order. Fails
+/// unless the tile lies within the image and `samples` has room for exactly
+/// its pixels.
+pub fn compute_tile<F: Formula>(samples: &mut [Option<Escape>], bounds: (usize, usize), tile: &Tile, viewport: &Viewport,
+                                params: &Params<F>, limit: usize, strategy: Strategy) -> Result<Stats, Error> {
+    let fits = |start: usize, len: usize, limit: usize| start.checked_add(len).is_some_and(|end| end <= limit);
+    if !fits(tile.left, tile.width, bounds.0) || !fits(tile.top, tile.height, bounds.1) {
+        return Err(Error::Invalid(format!("a {}x{} tile at ({}, {}) reaches outside a {}x{} image",
+                                          tile.width, tile.height, tile.left, tile.top, bounds.0, bounds.1)));
+    }
+    if samples.len() != tile.area() {
+        return Err(Error::Invalid(format!("{} samples can't hold a {}x{} tile", samples.len(), tile.width, tile.height)));
+    }
+    let mut stats = Stats::default();
+    let mut evaluate = |i: usize| params.iterate(pixel_to_point(bounds, tile.pixel(i), viewport), limit, &mut stats);
+    match strategy {
+        Strategy::MarianiSilver => {
+            stats.filled = mariani_silver(samples, tile.width, &mut evaluate)?;
+            return Ok(stats);
+        }
+        Strategy::BoundaryTrace => {
+            stats.filled = boundary_trace(samples, tile.width, &mut evaluate)?;
+            return Ok(stats);
+        }
+        Strategy::BruteForce => {}
+    }
+    for (group, chunk) in samples.chunks_mut(LANES).enumerate() {
+        let point = |lane: usize| pixel_to_point(bounds, tile.pixel(group * LANES + lane), viewport);
+        if chunk.len() == LANES {
+            let points = std::array::from_fn(point);
+            chunk.copy_from_slice(&params.iterate_lanes(points, limit, &mut stats));
+        } else {
+            for (lane, sample) in chunk.iter_mut().enumerate() {
+                *sample = params.iterate(point(lane), limit, &mut stats);
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// The outcome of [`render_tiled`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tiled {
+    /// The samples of every finished tile; the rest are `None`.
+    pub field: Field,
+    /// Iterations done and saved.
+    pub stats: Stats,
+    /// Which pixels belong to finished tiles. A render stopped early leaves
+    /// the rest `false`, so they can be told apart from the set's interior.
+    pub done: Image<bool>,
+}
+
+impl Tiled {
+    /// Whether every tile was finished.
+    pub fn is_complete(&self) -> bool {
+        self.done.iter().all(|&done| done)
+    }
+}
+
+/// Computes the field for an image of `bounds` pixels tile by tile.
+///
+/// Each of rayon's threads takes the next tile in `tiling.order` whenever it
+/// finishes one. `on_tile` is called with every finished tile and its
+/// samples, on the thread that computed it; returning `false` stops further
+/// tiles from being started, leaving them out of [`Tiled::done`]. Fails if
+/// `tiling.size` is 0 or the image has more pixels than a `usize` can
+/// count.
+pub fn render_tiled<F, C>(bounds: (usize, usize), viewport: &Viewport, params: &Params<F>, limit: usize, tiling: &Tiling,
+                          on_tile: C) -> Result<Tiled, Error>
+    where F: Formula + Sync,
+          C: Fn(&Tile, &[Option<Escape>]) -> bool + Sync
+{
+    let mut queue = tiles(bounds, tiling.size)?;
+    schedule(&mut queue, tiling.order, bounds, viewport, params, limit);
+
+    let field = Mutex::new((Image::new(bounds)?, Image::new(bounds)?));
+    let next = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let stats = (0..rayon::current_num_threads()).into_par_iter()
+        .map(|_| -> Result<Stats, Error> {
+            let mut stats = Stats::default();
+            let mut samples = Vec::new();
+            while !stopped.load(Ordering::Relaxed) {
+                let tile = match queue.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    Some(tile) => tile,
+                    None => break,
+                };
+                samples.resize(tile.area(), None);
+                stats += compute_tile(&mut samples, bounds, tile, viewport, params, limit, tiling.strategy)?;
+
+                {
+                    let (field, done) = &mut *field.lock().unwrap();
+                    field.view_mut(tile.left, tile.top, (tile.width, tile.height))?.copy_from(&samples)?;
+                    done.view_mut(tile.left, tile.top, (tile.width, tile.height))?.copy_from(&vec![true; tile.area()])?;
+                }
+
+                if !on_tile(tile, &samples) {
+                    stopped.store(true, Ordering::Relaxed);
+                }
+            }
+            Ok(stats)
+        })
+        .try_reduce(Stats::default, |mut a, b| { a += b; Ok(a) })?;
+
+    let (samples, done) = field.into_inner().unwrap();
+    Ok(Tiled { field: Field { limit, samples }, stats, done })
+}
+
+#[test]
+fn test_render_tiled() {
+    let params = Params { formula: crate::Fractal::Mandelbrot, mode: crate::Mode::Mandelbrot, radius: 256.0, interior_checks: true };
+    let bounds = (70, 45);
+    let viewport = Viewport::from_zoom(Complex { re: -0.7, im: 0.2 }, 3.0, 0.0).fit(bounds);
+
+    let whole = render_tiled(bounds, &viewport, &params, 200, &Tiling { size: 1000, order: TileOrder::Rows, ..Tiling::default() }, |_, _| true).unwrap();
+    assert!(whole.is_complete());
+    let rows = whole.field;
+    for order in [TileOrder::Center, TileOrder::Cost, TileOrder::Rows] {
+        let tiled = render_tiled(bounds, &viewport, &params, 200, &Tiling { size: 16, order, ..Tiling::default() }, |_, _| true).unwrap();
+        assert_eq!(tiled.field, rows);
+    }
+
+    // Stopping after the first tile marks just that tile done, leaving the
+    // rest of the field untouched.
+    let one_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let partial = one_thread.install(|| {
+        render_tiled(bounds, &viewport, &params, 200, &Tiling { size: 16, order: TileOrder::Rows, ..Tiling::default() }, |_, _| false)
+    }).unwrap();
+    assert!(!partial.is_complete());
+    let first = tiles(bounds, 16).unwrap()[0];
+    for i in 0..partial.done.len() {
+        let (x, y) = (i % bounds.0, i / bounds.0);
+        let inside = x < first.width && y < first.height;
+        assert_eq!(partial.done[i], inside);
+        assert_eq!(partial.field.samples[i], if inside { rows.samples[i] } else { None });
+    }
+
+    let zero = Tiling { size: 0, ..Tiling::default() };
+    assert!(matches!(render_tiled(bounds, &viewport, &params, 200, &zero, |_, _| true), Err(Error::Invalid(_))));
+    let outside = Tile { left: 60, top: 0, width: 16, height: 16 };
+    let mut samples = vec![None; outside.area()];
+    assert!(matches!(compute_tile(&mut samples, bounds, &outside, &viewport, &params, 200, Strategy::BruteForce), Err(Error::Invalid(_))));
+    assert!(matches!(compute_tile(&mut samples[1..], bounds, &tiles(bounds, 16).unwrap()[0], &viewport, &params, 200, Strategy::BruteForce),
+                     Err(Error::Invalid(_))));
+}
@@ -0,0 +1,228 @@
+// The view the interactive front ends, the explorer window and the terminal
+// preview, move around.
+//
+// It is held as a render job, the options the command line started with as
+// changed since, so what it shows is exactly what the same options render,
+// and exporting is rendering that job at full size like any other. The
+// center is kept as an f64, so panning very deep views moves in steps of
+// its precision.
+
+use crate::cli::{clap_error, Args};
+use crate::{compute_field, job, render};
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use mandelbrot::animation::frame_filename;
+use mandelbrot::{colorize, Error, Image, Viewport};
+use std::path::Path;
+use toml::{Table, Value};
+
+// The fractals a front end steps through.
+pub const FRACTALS: &[&str] = &["mandelbrot", "multibrot:3", "burning-ship", "tricorn", "celtic"];
+
+// Parses `job` as the command line it stands for.
+fn parse_args(job: &Table) -> Result<Args, Error> {
+    let argv = std::iter::once("mandelbrot".to_string()).chain(job::to_args(job)?);
+    let matches = Args::command().try_get_matches_from(argv).map_err(clap_error)?;
+    Args::from_arg_matches(&matches).map_err(clap_error)
+}
+
+pub struct View {
+    // The options shown, at full size.
+    job: Table,
+    // The options to go back to.
+    start: Table,
+    // The size of the display in pixels.
+    bounds: (usize, usize),
+    // The view, fitted to the display.
+    viewport: Viewport,
+    // The iteration limit of the last render.
+    limit: usize,
+    // How many exports have been numbered so far.
+    exports: usize,
+}
+
+impl View {
+    // The view `matches` describes, on a display of `bounds` pixels, with
+    // exports numbered after `default_output` if there is no --output.
+    pub fn new(matches: &ArgMatches, bounds: (usize, usize), default_output: &str) -> Result<View, Error> {
+        let mut job = job::effective(matches);
+        job.entry("output").or_insert_with(|| Value::String(default_output.to_string()));
+        let mut args = parse_args(&job)?;
+        args.validate()?;
+        args.size = bounds;
+        let viewport = args.viewport()?;
+        let mut view = View { start: job.clone(), job, bounds, viewport, limit: 0, exports: 0 };
+        view.set_viewport(viewport);
+        Ok(view)
+    }
+
+    pub fn option(&self, key: &str) -> &str {
+        self.job.get(key).and_then(Value::as_str).unwrap_or_default()
+    }
+
+    fn set_option(&mut self, key: &str, value: String) {
+        self.job.insert(key.to_string(), Value::String(value));
+    }
+
+    // The job's options for an image of `bounds` pixels.
+    fn args(&self, bounds: (usize, usize)) -> Result<Args, Error> {
+        let mut job = self.job.clone();
+        job.insert("size".to_string(), Value::String(format!("{}x{}", bounds.0, bounds.1)));
+        parse_args(&job)
+    }
+
+    pub fn bounds(&self) -> (usize, usize) {
+        self.bounds
+    }
+
+    // Moves to `viewport`, recording it in the job by its center and zoom.
+    fn set_viewport(&mut self, viewport: Viewport) {
+        for key in ["upper-left", "lower-right", "width"] {
+            self.job.remove(key);
+        }
+        self.set_option("center", format!("{},{}", viewport.center.re, viewport.center.im));
+        self.set_option("zoom", viewport.zoom().to_string());
+        self.set_option("rotate", viewport.rotation.to_degrees().to_string());
+        self.viewport = viewport;
+    }
+
+    // Goes back to the starting view and options.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.job = self.start.clone();
+        let viewport = self.args(self.bounds)?.viewport()?;
+        self.set_viewport(viewport);
+        Ok(())
+    }
+
+    // Changes the display to `bounds` pixels, keeping the center and zoom.
+    pub fn resize(&mut self, bounds: (usize, usize)) {
+        let viewport = &self.viewport;
+        self.bounds = bounds;
+        self.viewport = Viewport::from_zoom(viewport.center, viewport.zoom(), viewport.rotation).fit(bounds);
+    }
+
+    // Follows a drag of `delta` display pixels.
+    pub fn pan(&mut self, delta: (f64, f64)) {
+        self.set_viewport(self.viewport.pan(self.bounds, delta));
+    }
+
+    // Magnifies `factor` times about `position` on the display.
+    pub fn zoom(&mut self, position: (f64, f64), factor: f64) {
+        self.set_viewport(self.viewport.zoom_at(self.bounds, position, factor));
+    }
+
+    // Magnifies `factor` times about the middle of the display.
+    pub fn zoom_middle(&mut self, factor: f64) {
+        self.zoom((self.bounds.0 as f64 / 2.0, self.bounds.1 as f64 / 2.0), factor);
+    }
+
+    // Multiplies the iteration limit by `factor`, fixing it if it was `auto`.
+    pub fn scale_limit(&mut self, factor: f64) -> Result<(), Error> {
+        let limit = self.args(self.bounds)?.max_iter.resolve(&self.viewport);
+        self.set_option("max-iter", ((limit as f64 * factor).round().max(1.0) as usize).to_string());
+        Ok(())
+    }
+
+    // Sets `key` to the choice after its value, or the first if it has none
+    // of them.
+    pub fn cycle(&mut self, key: &str, choices: &[&str]) {
+        let next = choices.iter().position(|choice| self.option(key) == *choice).map_or(0, |i| (i + 1) % choices.len());
+        self.set_option(key, choices[next].to_string());
+    }
+
+    // Renders the view as an image of `bounds` pixels, which need only have
+    // the display's shape, not its size.
+    pub fn render(&mut self, bounds: (usize, usize)) -> Result<Image<[u8; 3]>, Error> {
+        let mut args = self.args(bounds)?;
+        // The display isn't supersampled, whatever the engine.
+        args.aa = 1;
+        args.validate()?;
+        self.limit = args.max_iter.resolve(&self.viewport);
+        let center = |bits| args.precise_center(&self.viewport, bits);
+        let field = compute_field(&args, &args.params(), &self.viewport, self.limit, center, None, false)?;
+        Ok(colorize(&field, &args.colors()?))
+    }
+
+    // Renders the view at full size, with every option it was started with,
+    // to the next unused name numbered after the output, and writes its job
+    // beside it. Returns the image's name.
+    pub fn export(&mut self) -> Result<String, Error> {
+        let filename = loop {
+            self.exports += 1;
+            let filename = frame_filename(self.option("output"), self.exports);
+            if !Path::new(&filename).exists() {
+                break filename;
+            }
+        };
+        let mut job = self.job.clone();
+        job.insert("output".to_string(), Value::String(filename.clone()));
+        let args = parse_args(&job)?;
+        args.validate()?;
+        render(&args, &job)?;
+        job::write(&filename, &job)?;
+        Ok(filename)
+    }
+
+    // The options that put the view in a full-size image: its corners, or
+    // its center, zoom and rotation if it is turned, which corners can't
+    // describe.
+    pub fn view_options(&self) -> Result<String, Error> {
+        let size = parse_args(&self.job)?.size;
+        let viewport = Viewport::from_zoom(self.viewport.center, self.viewport.zoom(), self.viewport.rotation).fit(size);
+        if viewport.rotation != 0.0 {
+            return Ok(format!("--center={} --zoom={} --rotate={}", self.option("center"), self.option("zoom"),
+                              self.option("rotate")));
+        }
+        let (upper_left, lower_right) = viewport.corners();
+        Ok(format!("--upper-left={},{} --lower-right={},{}", upper_left.re, upper_left.im, lower_right.re, lower_right.im))
+    }
+
+    // A line describing the view.
+    pub fn status(&self) -> String {
+        format!("{} at {},{}, zoom {:.4e}, {} iterations, {}", self.option("fractal"), self.viewport.center.re,
+                self.viewport.center.im, self.viewport.zoom(), self.limit, self.option("palette"))
+    }
+}
+
+#[test]
+fn test_view() {
+    use mandelbrot::palette::BUILTIN_NAMES;
+
+    let matches = Args::command().get_matches_from(["mandelbrot", "-o", "view.png", "-s", "80x40",
+                                                    "--upper-left=-2,1", "--lower-right=2,-1", "--aa", "2"]);
+    let mut view = View::new(&matches, (80, 40), "default.png").unwrap();
+    assert!(!view.job.contains_key("upper-left"));
+    assert_eq!(view.option("zoom"), "2");
+    assert_eq!(view.view_options().unwrap(), "--upper-left=-2,1 --lower-right=2,-1");
+
+    view.zoom((20.0, 10.0), 4.0);
+    view.pan((8.0, -4.0));
+    let viewport = view.args((80, 40)).unwrap().viewport().unwrap();
+    assert!((viewport.center - view.viewport.center).norm() < 1e-12);
+    assert!((viewport.zoom() - 8.0).abs() < 1e-9);
+
+    view.cycle("palette", BUILTIN_NAMES);
+    view.cycle("fractal", FRACTALS);
+    assert_eq!((view.option("palette"), view.option("fractal")), ("grayscale", "multibrot:3"));
+    view.scale_limit(2.0).unwrap();
+    assert_eq!(view.option("max-iter"), "510");
+
+    let coarse = view.render((10, 5)).unwrap();
+    let fine = view.render((80, 40)).unwrap();
+    assert_eq!((coarse.bounds(), fine.bounds()), ((10, 5), (80, 40)));
+    assert!(fine.rows().flatten().any(|pixel| *pixel != fine[0]));
+
+    view.reset().unwrap();
+    assert_eq!((view.option("palette"), view.option("zoom")), ("classic", "2"));
+    view.resize((40, 40));
+    assert_eq!(view.viewport.width, 2.0);
+    assert_eq!(view.view_options().unwrap(), "--upper-left=-2,1 --lower-right=2,-1");
+
+    let path = std::env::temp_dir().join("twod-test-view.png");
+    view.set_option("output", path.to_str().unwrap().to_string());
+    let filename = view.export().unwrap();
+    assert!(filename.ends_with("twod-test-view-0001.png"));
+    let written = job::load(&format!("{}.toml", filename)).unwrap().remove(0);
+    assert!(written.contains(&"--aa=2".to_string()) && written.contains(&"--zoom=2".to_string()));
+    std::fs::remove_file(&filename).unwrap();
+    std::fs::remove_file(format!("{}.toml", filename)).unwrap();
+}
@@ -0,0 +1,154 @@
+//! Mapping image pixels to points in the complex plane.
+
+use num::Complex;
+
+/// The region of the complex plane an image shows: a rectangle of `width` by
+/// `height` around `center`, turned counter-clockwise by `rotation` radians.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// The point at the middle of the image.
+    pub center: Complex<f64>,
+    /// Extent along the image's horizontal axis.
+    pub width: f64,
+    /// Extent along the image's vertical axis.
+    pub height: f64,
+    /// Counter-clockwise turn in radians.
+    pub rotation: f64,
+}
+
+impl Viewport {
+    /// The unrotated view between two corners.
+    pub fn from_corners(upper_left: Complex<f64>, lower_right: Complex<f64>) -> Viewport {
+        Viewport {
+            center: (upper_left + lower_right) / 2.0,
+            width: lower_right.re - upper_left.re,
+            height: upper_left.im - lower_right.im,
+            rotation: 0.0,
+        }
+    }
+
+    /// A square view `4 / zoom` units across; [`Viewport::fit`] stretches it
+    /// to the image.
+    pub fn from_zoom(center: Complex<f64>, zoom: f64, rotation: f64) -> Viewport {
+        Viewport { center, width: 4.0 / zoom, height: 4.0 / zoom, rotation }
+    }
+
+    /// Magnification relative to a view four units across.
+    pub fn zoom(&self) -> f64 {
+        4.0 / self.width.min(self.height)
+    }
+
+    /// The distance between neighbouring pixels in an image of `bounds`
+    /// pixels, along whichever axis has them closer.
+    pub fn pixel_size(&self, bounds: (usize, usize)) -> f64 {
+        (self.width / bounds.0 as f64).min(self.height / bounds.1 as f64)
+    }
+
+    /// Widens or heightens the view, keeping its center, until its aspect
+    /// ratio matches `bounds`, so pixels come out square and everything the
+    /// view asked for stays visible.
+    pub fn fit(self, bounds: (usize, usize)) -> Viewport {
+        let aspect = bounds.0 as f64 / bounds.1 as f64;
+        if self.width / self.height < aspect {
+            Viewport { width: self.height * aspect, ..self }
+        } else {
+            Viewport { height: self.width / aspect, ..self }
+        }
+    }
+
+    /// The view moved to follow a drag of `delta` pixels across an image of
+    /// `bounds` pixels, so what was under the pointer stays under it.
+    pub fn pan(self, bounds: (usize, usize), delta: (f64, f64)) -> Viewport {
+        let middle = (bounds.0 as f64 / 2.0, bounds.1 as f64 / 2.0);
+        let shift = position_to_offset(bounds, (middle.0 - delta.0, middle.1 - delta.1), &self);
+        Viewport { center: self.center + shift, ..self }
+    }
+
+    /// The view magnified `factor` times about `position` in an image of
+    /// `bounds` pixels, keeping the point there in place. Factors below one
+    /// zoom out.
+    pub fn zoom_at(self, bounds: (usize, usize), position: (f64, f64), factor: f64) -> Viewport {
+        let offset = position_to_offset(bounds, position, &self);
+        Viewport {
+            center: self.center + offset - offset / factor,
+            width: self.width / factor,
+            height: self.height / factor,
+            ..self
+        }
+    }
+
+    /// The upper left and lower right corners, ignoring rotation.
+    pub fn corners(&self) -> (Complex<f64>, Complex<f64>) {
+        let half = Complex { re: self.width / 2.0, im: self.height / 2.0 };
+        (Complex { re: self.center.re - half.re, im: self.center.im + half.im },
+         Complex { re: self.center.re + half.re, im: self.center.im - half.im })
+    }
+}
+
+/// The point under the upper left corner of `pixel` in an image of `bounds`
+/// pixels showing `viewport`.
+pub fn pixel_to_point(bounds: (usize, usize), pixel: (usize, usize), viewport: &Viewport) -> Complex<f64> {
+    position_to_point(bounds, (pixel.0 as f64, pixel.1 as f64), viewport)
+}
+
+/// Like [`pixel_to_point`], for fractional pixel coordinates.
+pub fn position_to_point(bounds: (usize, usize), position: (f64, f64), viewport: &Viewport) -> Complex<f64> {
+    viewport.center + position_to_offset(bounds, position, viewport)
+}
+
+/// How far the point under `position` lies from the center of the view.
+/// Deep zooms add this to a center held in higher precision.
+pub fn position_to_offset(bounds: (usize, usize), position: (f64, f64), viewport: &Viewport) -> Complex<f64> {
+    let offset = Complex {
+        re: (position.0 / bounds.0 as f64 - 0.5) * viewport.width,
+        im: (0.5 - position.1 / bounds.1 as f64) * viewport.height,
+    };
+    if viewport.rotation == 0.0 {
+        return offset;
+    }
+    offset * Complex::from_polar(1.0, viewport.rotation)
+}
+
+#[test]
+fn test_pixel_to_point() {
+    let viewport = Viewport::from_corners(Complex { re: -1.0, im:  1.0 },
+                                          Complex { re:  1.0, im: -1.0 });
+    assert_eq!(pixel_to_point((100, 200), (25, 175), &viewport),
+               Complex { re: -0.5, im: -0.75 });
+
+    let rotated = Viewport { rotation: std::f64::consts::FRAC_PI_2, ..viewport };
+    let point = pixel_to_point((100, 200), (25, 175), &rotated);
+    assert!((point - Complex { re: 0.75, im: -0.5 }).norm() < 1e-12);
+}
+
+#[test]
+fn test_viewport_fit() {
+    let viewport = Viewport::from_zoom(Complex { re: -0.5, im: 0.0 }, 2.0, 0.0);
+    let wide = viewport.fit((400, 200));
+    assert_eq!((wide.center, wide.width, wide.height), (viewport.center, 4.0, 2.0));
+    let tall = viewport.fit((200, 400));
+    assert_eq!((tall.center, tall.width, tall.height), (viewport.center, 2.0, 4.0));
+
+    let upper_left = Complex { re: -2.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+    let fitted = Viewport::from_corners(upper_left, lower_right).fit((300, 200));
+    assert_eq!(pixel_to_point((300, 200), (0, 0), &fitted), upper_left);
+    assert_eq!(pixel_to_point((300, 200), (300, 200), &fitted), lower_right);
+    assert_eq!(fitted.zoom(), 2.0);
+    assert_eq!(fitted.corners(), (upper_left, lower_right));
+}
+
+#[test]
+fn test_viewport_pan_and_zoom() {
+    let bounds = (400, 300);
+    let viewport = Viewport { rotation: 0.5, ..Viewport::from_zoom(Complex { re: -0.5, im: 0.2 }, 2.0, 0.0).fit(bounds) };
+
+    let panned = viewport.pan(bounds, (30.0, -20.0));
+    let before = position_to_point(bounds, (100.0, 100.0), &viewport);
+    assert!((position_to_point(bounds, (130.0, 80.0), &panned) - before).norm() < 1e-12);
+
+    let zoomed = viewport.zoom_at(bounds, (100.0, 100.0), 8.0);
+    assert!((position_to_point(bounds, (100.0, 100.0), &zoomed) - before).norm() < 1e-12);
+    assert!((zoomed.zoom() - 8.0 * viewport.zoom()).abs() < 1e-9);
+    assert_eq!(viewport.zoom_at(bounds, (200.0, 150.0), 0.5).center, viewport.center);
+}